@@ -0,0 +1,253 @@
+use alloc::sync::Arc;
+use core::time::Duration;
+use std::collections::HashMap;
+
+use actix::prelude::{
+    Actor, ActorFutureExt, ArbiterService, AsyncContext, Context, ContextFutureSpawner, Handler,
+    Message, ResponseActFuture, Supervised, WrapFuture,
+};
+use serde::{Deserialize, Serialize};
+use twilight_http::Client;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::Id;
+
+use crate::command::format_track_status;
+use crate::connection::{Connector, CurrentStatus, GetCurrentStatus, TrackData};
+use crate::settings::{GetGuildSettings, Settings};
+use crate::util::{token, Pipe};
+
+const STATUS_INTERVAL: Duration = Duration::from_secs(15);
+
+/// saved with settings of the guild
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnnounceSettings {
+    /// posts "now playing" when a track started
+    pub now_playing: bool,
+    /// keeps a status message updated
+    pub status: bool,
+}
+impl Default for AnnounceSettings {
+    fn default() -> Self {
+        Self {
+            now_playing: true,
+            status: false,
+        }
+    }
+}
+
+/// posts to the channels where tracks were enqueued
+///
+/// settings are owned by `Settings`
+pub struct Announcer {
+    client: Arc<Client>,
+    // guild -> (channel, message)
+    statuses: HashMap<u64, (u64, u64)>,
+}
+impl Announcer {
+    async fn settings(guild: u64) -> AnnounceSettings {
+        Settings::from_registry()
+            .send(GetGuildSettings { guild })
+            .await
+            .expect("failed sending")
+            .announce
+    }
+
+    fn post(&self, channel: u64, content: String, ctx: &mut Context<Self>) {
+        let client = self.client.clone();
+
+        async move {
+            let result: Result<_, String> = try {
+                client
+                    .create_message(Id::new(channel))
+                    .content(&content)
+                    .map_err(|e| e.to_string())?
+                    .allowed_mentions(Some(&AllowedMentions::default()))
+                    .exec()
+                    .await
+                    .map_err(|e| e.to_string())?
+            };
+
+            if let Err(e) = result {
+                tracing::warn!("failed announcing to {}: {}", channel, e);
+            }
+        }
+        .into_actor(self)
+        .spawn(ctx);
+    }
+
+    fn create_status(&mut self, guild: u64, channel: u64, ctx: &mut Context<Self>) {
+        let client = self.client.clone();
+
+        async move {
+            let content = Self::status_content(guild).await?;
+
+            client
+                .create_message(Id::new(channel))
+                .content(&content)
+                .map_err(|e| e.to_string())?
+                .allowed_mentions(Some(&AllowedMentions::default()))
+                .exec()
+                .await
+                .map_err(|e| e.to_string())?
+                .model()
+                .await
+                .map_err(|e| e.to_string())?
+                .id
+                .get()
+                .pipe(Ok)
+        }
+        .into_actor(self)
+        .map(move |result: Result<u64, String>, this, _| match result {
+            Ok(message) => {
+                this.statuses.insert(guild, (channel, message));
+            },
+            Err(e) => tracing::warn!("failed creating status of {}: {}", guild, e),
+        })
+        .spawn(ctx);
+    }
+
+    /// forgets the message if nothing is playing, failed editing, or disabled
+    fn refresh_status(&mut self, guild: u64, ctx: &mut Context<Self>) {
+        let (channel, message) = match self.statuses.get(&guild) {
+            Some(s) => *s,
+            None => return,
+        };
+        let client = self.client.clone();
+
+        async move {
+            if !Self::settings(guild).await.status {
+                return "disabled".to_string().pipe(Err);
+            }
+
+            let status = Self::status_content(guild).await;
+            let content = match &status {
+                Ok(c) => c.as_str(),
+                Err(_) => "nothing is playing",
+            };
+
+            let edited: Result<_, String> = try {
+                client
+                    .update_message(Id::new(channel), Id::new(message))
+                    .content(Some(content))
+                    .map_err(|e| e.to_string())?
+                    .allowed_mentions(Some(&AllowedMentions::default()))
+                    .exec()
+                    .await
+                    .map_err(|e| e.to_string())?
+            };
+
+            status.and(edited)
+        }
+        .into_actor(self)
+        .map(move |result, this, _| {
+            if result.is_err() {
+                this.statuses.remove(&guild);
+            }
+        })
+        .spawn(ctx);
+    }
+
+    async fn status_content(guild: u64) -> Result<String, String> {
+        let CurrentStatus { current_track } = Connector::from_registry()
+            .send(GetCurrentStatus { guild })
+            .await
+            .expect("failed sending")?;
+
+        format!("now playing:\n{}", format_track_status(current_track)).pipe(Ok)
+    }
+}
+impl Default for Announcer {
+    fn default() -> Self {
+        Self {
+            client: Client::new(token()).pipe(Arc::new),
+            statuses: HashMap::new(),
+        }
+    }
+}
+impl Actor for Announcer {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(STATUS_INTERVAL, |this, ctx| {
+            let guilds: Vec<_> = this.statuses.keys().copied().collect();
+            guilds
+                .into_iter()
+                .for_each(|guild| this.refresh_status(guild, ctx));
+        });
+    }
+}
+impl Handler<TrackNotice> for Announcer {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(
+        &mut self,
+        TrackNotice { guild, data, kind }: TrackNotice,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        Self::settings(guild)
+            .into_actor(self)
+            .map(move |settings, this, ctx| {
+                let channel = match data.channel {
+                    Some(c) => c,
+                    None => return this.refresh_status(guild, ctx),
+                };
+
+                let title = match data.title {
+                    Some(t) => t,
+                    None => data.source.to_string(),
+                };
+                if settings.now_playing {
+                    match kind {
+                        TrackNoticeKind::Started =>
+                            this.post(channel, format!("now playing: {}", title), ctx),
+                        TrackNoticeKind::Failed =>
+                            this.post(channel, format!("failed playing: {}", title), ctx),
+                        TrackNoticeKind::Finished => (),
+                    }
+                }
+
+                match (settings.status, this.statuses.contains_key(&guild), kind) {
+                    (false, ..) => {
+                        this.statuses.remove(&guild);
+                    },
+                    (true, true, _) => this.refresh_status(guild, ctx),
+                    (true, false, TrackNoticeKind::Started) =>
+                        this.create_status(guild, channel, ctx),
+                    (true, false, _) => (),
+                }
+            })
+            .pipe(Box::pin)
+    }
+}
+impl Supervised for Announcer {}
+impl ArbiterService for Announcer {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackNoticeKind {
+    Started,
+    /// ended or stopped
+    Finished,
+    /// ended far before its duration
+    Failed,
+}
+
+pub struct TrackNotice {
+    pub guild: u64,
+    pub data: TrackData,
+    pub kind: TrackNoticeKind,
+}
+impl Message for TrackNotice {
+    type Result = ();
+}
+
+/// `None` keeps the current value, and returns updated settings (handled by
+/// `Settings`)
+pub struct UpdateAnnounceSettings {
+    pub guild: u64,
+    pub now_playing: Option<bool>,
+    pub status: Option<bool>,
+}
+impl Message for UpdateAnnounceSettings {
+    type Result = Result<AnnounceSettings, String>;
+}
@@ -1,72 +1,243 @@
 use core::fmt::Write;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ops::Bound;
+use std::time::{Duration, UNIX_EPOCH};
 
 use actix::prelude::{Actor, ArbiterService, Context, Handler, Message, Supervised};
 use actix::ResponseFuture;
-use clap::{ArgGroup, Parser};
+use clap::{ArgEnum, ArgGroup, Parser};
 use url::Url;
 
+use crate::announce::{AnnounceSettings, UpdateAnnounceSettings};
+use crate::config::config;
 use crate::connection::{
-    CallAction, CallActionKind, Connector, ControlAction, ControlActionKind, CurrentStatus,
-    DropKind, GetCurrentStatus, GetHistoryStatus, GetQueueStatus, HistoryStatus, QueueStatus,
-    TrackInfo, TrackStatus,
+    BlockLoop, CallAction, CallActionKind, CallsStatus, Connector, ControlAction,
+    ControlActionKind, CurrentStatus, DropKind, GetCallsStatus, GetCurrentStatus, GetHistoryStatus,
+    GetQueueStatus, HistoryStatus, LoopCount, LoopTarget, QueueStatus, TrackData, TrackInfo,
+    TrackStatus,
 };
-use crate::gateway::{MessageRef, RawCommand};
-use crate::util::{reply, reply_err, Pipe};
+use crate::gateway::{Attachment, ButtonData, EmbedData, EmbedField, MessageRef, RawCommand};
+use crate::permission::{
+    Access, CheckPermission, PermissionSettings, PermissionUpdate, Permissions, PermissionsStatus,
+    ResolveRole, Rule, UpdatePermissions,
+};
+use crate::presence::{self, Timeout, UpdatePolicy};
+use crate::repository::{
+    DeleteTrack, ListTracks, RenameTrack, Repository, SetVisibility, ShareTarget, ShareTrack,
+    StoredTrack, Upload, Visibility,
+};
+use crate::search::{self, SearchResult, Searcher};
+use crate::settings::{
+    GetGuildSettings, GuildSettings, ResetSettings, SetAlias, SetSetting, SettingKey, Settings,
+};
+use crate::source::{self, Source};
+use crate::timespec::{self, TimeSpec};
+use crate::util::{reply, reply_embed, reply_err, Pipe};
+use crate::voice::{GetUserChannel, ResolveChannel, VoiceStates};
 
-#[derive(Default)]
-pub struct CommandParser;
+const EMBED_COLOR: u32 = 0x5865f2;
+const PROGRESS_WIDTH: usize = 12;
+const PAGED_COMMANDS: &[&str] = &["show-queue", "show-history"];
+
+pub struct CommandParser {
+    /// id of the bot, to be invoked by mentions
+    bot: u64,
+}
+impl CommandParser {
+    pub fn new(bot: u64) -> Self { Self { bot } }
+}
+impl Default for CommandParser {
+    fn default() -> Self { panic!("cannot initialize on Default::default") }
+}
 impl Actor for CommandParser {
     type Context = Context<Self>;
 }
 impl Handler<RawCommand> for CommandParser {
-    type Result = ();
+    type Result = ResponseFuture<()>;
 
     fn handle(
         &mut self,
         RawCommand {
             content,
+            attachments,
             from,
-            user: _,
+            user,
             guild,
+            roles,
         }: RawCommand,
         _: &mut Self::Context,
     ) -> Self::Result {
-        let result: Result<_, String> = try {
-            let split = shell_words::split(&content).map_err(|e| e.to_string())?;
+        let bot = self.bot;
 
-            match split.get(0).map(|s| s.as_str()) {
-                Some("*v") => (),
-                _ => return,
-            }
-
-            match guild {
-                None => {
-                    let PrivateCommandParser { cmd: _ } =
-                        PrivateCommandParser::try_parse_from(split).map_err(|e| e.to_string())?;
-
-                    unimplemented!();
-                },
+        async move {
+            let (prefixes, prefixless, aliases) = match guild {
                 Some(guild) => {
-                    let GuildCommandParser { cmd } =
-                        GuildCommandParser::try_parse_from(split).map_err(|e| e.to_string())?;
-
-                    GuildCommandProcesser::from_registry()
-                        .try_send(GuildCommandData { cmd, guild, from })
+                    let settings = Settings::from_registry()
+                        .send(GetGuildSettings { guild })
+                        .await
                         .expect("failed sending");
+                    let prefixless = settings.music_channel == Some(from.channel());
+
+                    (settings.prefixes(), prefixless, settings.aliases)
                 },
+                None => (vec![config().prefix.clone()], false, BTreeMap::new()),
+            };
+
+            let result: Result<_, String> = try {
+                let split = shell_words::split(&content).map_err(|e| e.to_string())?;
+
+                let split = match strip_invocation(split, &prefixes, bot, prefixless, &aliases) {
+                    Some(s) => expand_alias(s, &aliases),
+                    None => return,
+                };
+
+                match guild {
+                    None => {
+                        let PrivateCommandParser { cmd } =
+                            PrivateCommandParser::try_parse_from(split)
+                                .map_err(|e| e.to_string())?;
+
+                        PrivateCommandProcesser::from_registry()
+                            .try_send(PrivateCommandData {
+                                cmd,
+                                attachments,
+                                user,
+                                from,
+                            })
+                            .expect("failed sending");
+                    },
+                    Some(guild) => {
+                        let GuildCommandParser { cmd } =
+                            GuildCommandParser::try_parse_from(split).map_err(|e| e.to_string())?;
+
+                        GuildCommandProcesser::from_registry()
+                            .try_send(GuildCommandData {
+                                cmd,
+                                attachments,
+                                guild,
+                                user,
+                                roles,
+                                from,
+                            })
+                            .expect("failed sending");
+                    },
+                }
+            };
+
+            match result {
+                Ok(o) => o,
+                Err(e) => reply_err(e, from),
             }
+        }
+        .pipe(Box::pin)
+    }
+}
+impl Handler<InteractionCommand> for CommandParser {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        InteractionCommand {
+            args,
+            attachments,
+            user,
+            guild,
+            roles,
+            from,
+        }: InteractionCommand,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let args = core::iter::once("/".to_string()).chain(args);
+        let cmd = match GuildCommandParser::try_parse_from(args) {
+            Ok(GuildCommandParser { cmd }) => cmd,
+            Err(e) => return reply_err(e, from),
         };
 
-        match result {
-            Ok(o) => o,
-            Err(e) => reply_err(e, from),
-        }
+        GuildCommandProcesser::from_registry()
+            .try_send(GuildCommandData {
+                cmd,
+                attachments,
+                guild,
+                user,
+                roles,
+                from,
+            })
+            .expect("failed sending");
     }
 }
 impl Supervised for CommandParser {}
 impl ArbiterService for CommandParser {}
 
+/// keeps the invocation as the binary name, or `None` if not invoked
+///
+/// invoked by one of `prefixes`, a mention of `bot`, or (if `prefixless`) a
+/// name or an alias of guild commands
+fn strip_invocation(
+    mut words: Vec<String>,
+    prefixes: &[String],
+    bot: u64,
+    prefixless: bool,
+    aliases: &BTreeMap<String, Vec<String>>,
+) -> Option<Vec<String>> {
+    let first = words.first()?;
+
+    let mentions = [format!("<@{}>", bot), format!("<@!{}>", bot)];
+    if prefixes.contains(first) || mentions.contains(first) {
+        return Some(words);
+    }
+
+    let is_command = is_builtin_command(first) || aliases.contains_key(first);
+    if prefixless && is_command {
+        words.insert(0, String::new());
+        return Some(words);
+    }
+
+    None
+}
+
+/// replaces the user-defined alias following the binary name
+fn expand_alias(words: Vec<String>, aliases: &BTreeMap<String, Vec<String>>) -> Vec<String> {
+    let expansion = match words.get(1).and_then(|w| aliases.get(w)) {
+        Some(e) => e,
+        None => return words,
+    };
+
+    let mut words = words.into_iter();
+    words
+        .next()
+        .into_iter()
+        .chain(expansion.iter().cloned())
+        .chain(words.skip(1))
+        .collect()
+}
+
+/// a name or a built-in alias of guild commands
+fn is_builtin_command(name: &str) -> bool {
+    guild_command()
+        .get_subcommands()
+        .any(|c| c.get_name() == name || c.get_all_aliases().any(|a| a == name))
+}
+
+/// arguments converted from application command (without binary name)
+pub struct InteractionCommand {
+    pub args: Vec<String>,
+    pub attachments: Vec<Attachment>,
+    pub user: u64,
+    pub guild: u64,
+    pub roles: Option<Vec<u64>>,
+    pub from: MessageRef,
+}
+impl Message for InteractionCommand {
+    type Result = ();
+}
+
+/// definition of guild commands, for building application commands
+pub fn guild_command() -> clap::Command<'static> {
+    use clap::CommandFactory;
+
+    GuildCommandParser::command()
+}
+
 #[derive(Parser)]
 struct GuildCommandParser {
     #[clap(subcommand)]
@@ -75,29 +246,105 @@ struct GuildCommandParser {
 #[derive(Parser)]
 enum GuildCommand {
     Join {
-        channel: u64,
+        /// id, mention or name (the channel you are in if not specified)
+        channel: Option<String>,
     },
     Leave,
     Slide {
         from: usize,
         to: usize,
     },
-    #[clap(group = ArgGroup::new("items").required(true))]
+    #[clap(group = ArgGroup::new("kind").required(true))]
     Drop {
-        #[clap(short = 'i', long, group = "items")]
+        #[clap(short = 'i', long, group = "kind")]
         items: Option<usize>,
-        #[clap(short = 'r', long, group = "items", parse(try_from_str = range_parser::parse))]
+        #[clap(short = 'r', long, group = "kind", parse(try_from_str = range_parser::parse))]
         range: Option<(Bound<usize>, Bound<usize>)>,
     },
     Fix,
     Stop,
+    /// skips the current track, or `count` tracks from it (votes if enabled)
+    #[clap(visible_alias = "s")]
+    Skip {
+        #[clap(default_value = "1")]
+        count: usize,
+    },
+    /// skips tracks before the index of the queue
+    SkipTo {
+        index: usize,
+    },
+    /// shows or updates policies, e.g. "--leave-empty 10m" or "--leave-idle
+    /// off"
+    Policy {
+        /// leaves after the last listener left
+        #[clap(long)]
+        leave_empty: Option<Timeout>,
+        /// leaves after the queue became empty
+        #[clap(long)]
+        leave_idle: Option<Timeout>,
+        /// pauses while nobody is listening, and resumes when someone returns
+        #[clap(long, parse(try_from_str))]
+        pause_empty: Option<bool>,
+    },
+    /// shows or updates announcements, e.g. "--status true"
+    Announce {
+        /// posts "now playing" where the track was enqueued
+        #[clap(long, parse(try_from_str))]
+        now_playing: Option<bool>,
+        /// keeps a status message updated, from the next track
+        #[clap(long, parse(try_from_str))]
+        status: Option<bool>,
+    },
 
+    #[clap(visible_aliases = &["p", "play"])]
     Enqueue {
         url: Url,
+        /// entries of the playlist to enqueue (1-based)
+        #[clap(long, parse(try_from_str = range_parser::parse))]
+        items: Option<(Bound<usize>, Bound<usize>)>,
+    },
+    EnqueueLocal {
+        path: String,
+    },
+    Search {
+        #[clap(short = 'n', long, default_value = "5")]
+        count: usize,
+        #[clap(required = true)]
+        query: Vec<String>,
+    },
+    Pick {
+        index: usize,
+    },
+    /// plays the last finished track, then resumes the current one
+    Previous,
+    /// enqueues the track at the index of "show-history"
+    Replay {
+        index: usize,
+    },
+    /// enqueues tracks in the range of "show-history", oldest first
+    RequeueHistory {
+        #[clap(parse(try_from_str = range_parser::parse))]
+        range: (Bound<usize>, Bound<usize>),
     },
     Pause,
     Resume,
-    Loop,
+    #[clap(group = ArgGroup::new("target"))]
+    Loop {
+        /// index of the queue (0 is current)
+        #[clap(group = "target")]
+        index: Option<usize>,
+        /// loops tracks in the range as a block
+        #[clap(short = 'r', long, group = "target", parse(try_from_str = range_parser::parse))]
+        range: Option<(Bound<usize>, Bound<usize>)>,
+        /// loops all tracks in the queue as a block
+        #[clap(short = 'q', long, group = "target")]
+        queue: bool,
+        /// how many more times to play (infinite if not specified)
+        #[clap(short = 't', long)]
+        times: Option<usize>,
+        #[clap(long, conflicts_with = "times")]
+        off: bool,
+    },
     Shuffle,
     Volume {
         percent: f32,
@@ -105,28 +352,215 @@ enum GuildCommand {
     VolumeCurrent {
         percent: f32,
     },
+    Seek {
+        #[clap(allow_hyphen_values = true, parse(try_from_str = timespec::parse))]
+        time: TimeSpec,
+    },
 
+    #[clap(visible_alias = "np")]
     ShowCurrent,
+    #[clap(visible_alias = "q")]
     ShowQueue {
         page: Option<usize>,
     },
     ShowHistory {
         page: Option<usize>,
     },
+    /// shows or updates items per page of "show-queue" and "show-history"
+    PageSize {
+        size: Option<usize>,
+    },
+    /// shows or updates settings of the guild
+    Config {
+        #[clap(subcommand)]
+        cmd: ConfigCommand,
+    },
+    /// manages aliases of the guild, e.g. "set lofi enqueue (url)"
+    Alias {
+        #[clap(subcommand)]
+        cmd: AliasCommand,
+    },
+
+    Repo {
+        #[clap(subcommand)]
+        cmd: RepoCommand,
+    },
+
+    /// shows or updates permissions (administrators only)
+    Permission {
+        #[clap(subcommand)]
+        cmd: PermissionCommand,
+    },
+}
+impl GuildCommand {
+    /// name to check permissions with
+    fn name(&self) -> &'static str {
+        use GuildCommand::*;
+        match self {
+            Join { .. } => "join",
+            Leave => "leave",
+            Slide { .. } => "slide",
+            Drop { .. } => "drop",
+            Fix => "fix",
+            Stop => "stop",
+            Skip { .. } => "skip",
+            SkipTo { .. } => "skip-to",
+            Policy { .. } => "policy",
+            Announce { .. } => "announce",
+            Enqueue { .. } => "enqueue",
+            EnqueueLocal { .. } => "enqueue-local",
+            Search { .. } => "search",
+            Pick { .. } => "pick",
+            Previous => "previous",
+            Replay { .. } => "replay",
+            RequeueHistory { .. } => "requeue-history",
+            Pause => "pause",
+            Resume => "resume",
+            Loop { .. } => "loop",
+            Shuffle => "shuffle",
+            Volume { .. } => "volume",
+            VolumeCurrent { .. } => "volume-current",
+            Seek { .. } => "seek",
+            ShowCurrent => "show-current",
+            ShowQueue { .. } => "show-queue",
+            ShowHistory { .. } => "show-history",
+            PageSize { .. } => "page-size",
+            Config { .. } => "config",
+            Alias { .. } => "alias",
+            Repo { .. } => "repo",
+            Permission { .. } => "permission",
+        }
+    }
+}
+#[derive(Parser)]
+enum RepoCommand {
+    Upload {
+        name: Option<String>,
+    },
+    List,
+    Enqueue {
+        id: u64,
+    },
+    Rename {
+        id: u64,
+        name: String,
+    },
+    Delete {
+        id: u64,
+    },
+    Visibility {
+        id: u64,
+        #[clap(arg_enum)]
+        visibility: Visibility,
+    },
+    #[clap(group = ArgGroup::new("target").required(true))]
+    Share {
+        id: u64,
+        #[clap(long, group = "target")]
+        user: Option<u64>,
+        #[clap(long, group = "target")]
+        guild: Option<u64>,
+    },
+    #[clap(group = ArgGroup::new("target").required(true))]
+    Unshare {
+        id: u64,
+        #[clap(long, group = "target")]
+        user: Option<u64>,
+        #[clap(long, group = "target")]
+        guild: Option<u64>,
+    },
+}
+
+#[derive(Parser)]
+enum ConfigCommand {
+    /// shows the value of the key, or all if not specified
+    Get {
+        #[clap(arg_enum)]
+        key: Option<SettingKey>,
+    },
+    Set {
+        #[clap(arg_enum)]
+        key: SettingKey,
+        value: String,
+    },
+    /// resets the key to the default, or all if not specified
+    Reset {
+        #[clap(arg_enum)]
+        key: Option<SettingKey>,
+    },
 }
 
 #[derive(Parser)]
+enum AliasCommand {
+    List,
+    /// replaces the name with the command and its arguments, e.g. "lofi =
+    /// enqueue (url)"
+    Set {
+        name: String,
+        #[clap(required = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Parser)]
+enum PermissionCommand {
+    Show,
+    /// restricts controlling playback to the role, e.g. "DJ" or "--off"
+    #[clap(group = ArgGroup::new("target").required(true))]
+    DjRole {
+        /// id, mention or name
+        #[clap(group = "target")]
+        role: Option<String>,
+        #[clap(long, group = "target")]
+        off: bool,
+    },
+    /// allows the command only for allowed roles
+    Allow {
+        command: String,
+        /// id, mention, name or "everyone"
+        role: String,
+    },
+    /// denies the command for the role
+    Deny {
+        command: String,
+        /// id, mention, name or "everyone"
+        role: String,
+    },
+    /// removes allowed and denied roles of the command
+    Clear {
+        command: String,
+    },
+}
+
+#[derive(Parser)]
+#[clap(disable_help_subcommand = true)]
 struct PrivateCommandParser {
     #[clap(subcommand)]
     cmd: PrivateCommand,
 }
 #[derive(Parser)]
-enum PrivateCommand {}
+enum PrivateCommand {
+    Help,
+    Version,
+    Calls,
+    Guild {
+        guild: u64,
+        #[clap(subcommand)]
+        cmd: GuildCommand,
+    },
+}
 
 pub struct GuildCommandData {
     cmd: GuildCommand,
+    attachments: Vec<Attachment>,
     from: MessageRef,
     guild: u64,
+    user: u64,
+    /// fetched on checking permissions if `None`
+    roles: Option<Vec<u64>>,
 }
 impl Message for GuildCommandData {
     type Result = ();
@@ -142,19 +576,58 @@ impl Handler<GuildCommandData> for GuildCommandProcesser {
 
     fn handle(
         &mut self,
-        GuildCommandData { cmd, from, guild }: GuildCommandData,
+        GuildCommandData {
+            cmd,
+            attachments,
+            from,
+            guild,
+            user,
+            roles,
+        }: GuildCommandData,
         _: &mut Self::Context,
     ) -> Self::Result {
         async move {
+            let access = Permissions::from_registry()
+                .send(CheckPermission {
+                    guild,
+                    user,
+                    roles,
+                    command: cmd.name().to_string(),
+                })
+                .await
+                .expect("failed sending");
+            let access = match access {
+                Ok(a) => a,
+                Err(e) => return reply_err(e, from),
+            };
+
             use GuildCommand::*;
             match cmd {
-                Join { channel } => Connector::from_registry()
-                    .try_send(CallAction {
-                        kind: CallActionKind::Join { channel },
-                        from,
-                        guild,
-                    })
-                    .expect("failed sending"),
+                Join { channel } => {
+                    let channel = match channel {
+                        Some(query) => VoiceStates::from_registry()
+                            .send(ResolveChannel { guild, query })
+                            .await
+                            .expect("failed sending"),
+                        None => VoiceStates::from_registry()
+                            .send(GetUserChannel { guild, user })
+                            .await
+                            .expect("failed sending")
+                            .ok_or_else(|| "you are not in a voice channel".to_string()),
+                    };
+                    let channel = match channel {
+                        Ok(c) => c,
+                        Err(e) => return reply_err(e, from),
+                    };
+
+                    Connector::from_registry()
+                        .try_send(CallAction {
+                            kind: CallActionKind::Join { channel },
+                            from,
+                            guild,
+                        })
+                        .expect("failed sending")
+                },
                 Leave => Connector::from_registry()
                     .try_send(CallAction {
                         kind: CallActionKind::Leave,
@@ -182,9 +655,14 @@ impl Handler<GuildCommandData> for GuildCommandProcesser {
                         t => unreachable!("unexpected pattern: {:?}", t),
                     };
 
+                    let requester = match access {
+                        Access::OwnTracks => Some(user),
+                        _ => None,
+                    };
+
                     Connector::from_registry()
                         .try_send(CallAction {
-                            kind: CallActionKind::Drop { kind },
+                            kind: CallActionKind::Drop { kind, requester },
                             from,
                             guild,
                         })
@@ -192,7 +670,7 @@ impl Handler<GuildCommandData> for GuildCommandProcesser {
                 },
                 Fix => Connector::from_registry()
                     .try_send(CallAction {
-                        kind: CallActionKind::Fix,
+                        kind: CallActionKind::Fix { user },
                         from,
                         guild,
                     })
@@ -204,38 +682,214 @@ impl Handler<GuildCommandData> for GuildCommandProcesser {
                         guild,
                     })
                     .expect("failed sending"),
+                Skip { count } => Connector::from_registry()
+                    .try_send(CallAction {
+                        kind: CallActionKind::Skip {
+                            count,
+                            user,
+                            access,
+                        },
+                        from,
+                        guild,
+                    })
+                    .expect("failed sending"),
+                SkipTo { index: 0 } => reply_err("already playing the track", from),
+                SkipTo { index } => Connector::from_registry()
+                    .try_send(CallAction {
+                        kind: CallActionKind::Skip {
+                            count: index,
+                            user,
+                            access,
+                        },
+                        from,
+                        guild,
+                    })
+                    .expect("failed sending"),
+                Policy {
+                    leave_empty,
+                    leave_idle,
+                    pause_empty,
+                } => Settings::from_registry()
+                    .send(UpdatePolicy {
+                        guild,
+                        leave_empty,
+                        leave_idle,
+                        pause_empty,
+                    })
+                    .await
+                    .expect("failed sending")
+                    .map_err(|e| reply_err(e, from))
+                    .map(|p| reply(format_policy(p), from))
+                    .pipe(drop),
+                Announce {
+                    now_playing,
+                    status,
+                } => Settings::from_registry()
+                    .send(UpdateAnnounceSettings {
+                        guild,
+                        now_playing,
+                        status,
+                    })
+                    .await
+                    .expect("failed sending")
+                    .map_err(|e| reply_err(e, from))
+                    .map(
+                        |AnnounceSettings {
+                             now_playing,
+                             status,
+                         }| {
+                            format!("now-playing: {}\nstatus: {}", now_playing, status)
+                                .pipe(|msg| reply(msg, from))
+                        },
+                    )
+                    .pipe(drop),
+                Permission { cmd } => Self::permission(guild, cmd, from).await,
 
-                Enqueue { url } => Connector::from_registry()
+                Enqueue { url, items } if source::is_playlist(&url) => Connector::from_registry()
                     .try_send(ControlAction {
-                        kind: ControlActionKind::Enqueue {
+                        kind: ControlActionKind::EnqueuePlaylist {
                             url: url.to_string(),
+                            items,
+                            user,
                         },
                         from,
                         guild,
                     })
                     .expect("failed sending"),
+                Enqueue { items: Some(_), .. } => reply_err("not a playlist url", from),
+                Enqueue { url, items: None } => {
+                    let source = match url.scheme() {
+                        "file" => match source::local_path(&url) {
+                            Ok(path) => Source::Local { path },
+                            Err(e) => return reply_err(e, from),
+                        },
+                        _ => Source::Youtube {
+                            url: url.to_string(),
+                        },
+                    };
 
-                Pause => Connector::from_registry()
+                    Connector::from_registry()
+                        .try_send(ControlAction {
+                            kind: ControlActionKind::Enqueue { source, user },
+                            from,
+                            guild,
+                        })
+                        .expect("failed sending")
+                },
+                EnqueueLocal { path } => Connector::from_registry()
                     .try_send(ControlAction {
-                        kind: ControlActionKind::Pause,
+                        kind: ControlActionKind::Enqueue {
+                            source: Source::Local { path },
+                            user,
+                        },
                         from,
                         guild,
                     })
                     .expect("failed sending"),
-                Resume => Connector::from_registry()
+                Previous => Connector::from_registry()
                     .try_send(ControlAction {
-                        kind: ControlActionKind::Resume,
+                        kind: ControlActionKind::Previous { user },
+                        from,
+                        guild,
+                    })
+                    .expect("failed sending"),
+                Replay { index } => Connector::from_registry()
+                    .try_send(ControlAction {
+                        kind: ControlActionKind::Replay { index, user },
+                        from,
+                        guild,
+                    })
+                    .expect("failed sending"),
+                RequeueHistory { range } => Connector::from_registry()
+                    .try_send(ControlAction {
+                        kind: ControlActionKind::RequeueHistory { range, user },
+                        from,
+                        guild,
+                    })
+                    .expect("failed sending"),
+                Search { count, query } => Searcher::from_registry()
+                    .send(search::Search {
+                        guild,
+                        user,
+                        query: query.join(" "),
+                        count,
+                    })
+                    .await
+                    .expect("failed sending")
+                    .map_err(|e| reply_err(e, from))
+                    .map(|results| {
+                        let mut buf = String::new();
+                        results.into_iter().zip(1..).for_each(|(r, n)| {
+                            writeln!(buf, "{}. {}", n, format_search_result(r)).unwrap()
+                        });
+                        buf += "pick with \"pick (n)\"";
+                        buf
+                    })
+                    .map(|msg| reply(msg, from))
+                    .pipe(drop),
+                Pick { index } => {
+                    let picked = Searcher::from_registry()
+                        .send(search::Pick { guild, user, index })
+                        .await
+                        .expect("failed sending");
+
+                    match picked {
+                        Ok(SearchResult { url, .. }) => Connector::from_registry()
+                            .try_send(ControlAction {
+                                kind: ControlActionKind::Enqueue {
+                                    source: Source::Youtube { url },
+                                    user,
+                                },
+                                from,
+                                guild,
+                            })
+                            .expect("failed sending"),
+                        Err(e) => reply_err(e, from),
+                    }
+                },
+
+                Pause => Connector::from_registry()
+                    .try_send(ControlAction {
+                        kind: ControlActionKind::Pause,
                         from,
                         guild,
                     })
                     .expect("failed sending"),
-                Loop => Connector::from_registry()
+                Resume => Connector::from_registry()
                     .try_send(ControlAction {
-                        kind: ControlActionKind::Loop,
+                        kind: ControlActionKind::Resume,
                         from,
                         guild,
                     })
                     .expect("failed sending"),
+                Loop {
+                    index,
+                    range,
+                    queue,
+                    times,
+                    off,
+                } => {
+                    let target = match (index, range, queue) {
+                        (None, None, false) => LoopTarget::Index(0),
+                        (Some(i), None, false) => LoopTarget::Index(i),
+                        (None, Some(r), false) => LoopTarget::Range(r),
+                        (None, None, true) => LoopTarget::Queue,
+                        t => unreachable!("unexpected pattern: {:?}", t),
+                    };
+                    let count = match (times, off) {
+                        (_, true) => LoopCount::Off,
+                        (Some(n), false) => LoopCount::Finite(n),
+                        (None, false) => LoopCount::Infinite,
+                    };
+
+                    Connector::from_registry()
+                        .try_send(ControlAction {
+                            kind: ControlActionKind::Loop { target, count },
+                            from,
+                            guild,
+                        })
+                        .expect("failed sending")
+                },
                 Shuffle => Connector::from_registry()
                     .try_send(ControlAction {
                         kind: ControlActionKind::Shuffle,
@@ -263,16 +917,21 @@ impl Handler<GuildCommandData> for GuildCommandProcesser {
                         guild,
                     })
                     .expect("failed sending"),
+                Seek { time } => Connector::from_registry()
+                    .try_send(ControlAction {
+                        kind: ControlActionKind::Seek { time },
+                        from,
+                        guild,
+                    })
+                    .expect("failed sending"),
 
                 ShowCurrent => Connector::from_registry()
                     .send(GetCurrentStatus { guild })
                     .await
                     .expect("failed sending")
                     .map_err(|e| reply_err(e, from))
-                    .map(|CurrentStatus { current_track }| {
-                        format!("current:\n{}", format_track_status(current_track))
-                    })
-                    .map(|msg| reply(msg, from))
+                    .map(|CurrentStatus { current_track }| track_status_embed(current_track))
+                    .map(|embed| reply_embed(embed, from))
                     .pipe(drop),
                 ShowQueue { page } => Connector::from_registry()
                     .send(GetQueueStatus {
@@ -282,14 +941,45 @@ impl Handler<GuildCommandData> for GuildCommandProcesser {
                     .await
                     .expect("failed sending")
                     .map_err(|e| reply_err(e, from))
-                    .map(|QueueStatus { tracks }| {
-                        let mut buf = String::new();
-                        tracks.into_iter().for_each(|(i, ts)| {
-                            write!(buf, "{}:\n{}\n\n", i, format_track_status(ts)).unwrap();
-                        });
-                        buf
-                    })
-                    .map(|msg| reply(msg, from))
+                    .map(
+                        |QueueStatus {
+                             tracks,
+                             page,
+                             pages,
+                             count,
+                             remaining,
+                             unknown,
+                         }| {
+                            let mut header = format!(
+                                "page {} of {} / {} tracks / {} remaining",
+                                page,
+                                pages,
+                                count,
+                                format_duration(remaining)
+                            );
+                            if unknown != 0 {
+                                write!(header, " (+{} unknown)", unknown).unwrap();
+                            }
+
+                            let lines = tracks
+                                .into_iter()
+                                .map(|(i, ts)| format!("`{}` {}", i, format_track_line(ts)));
+
+                            EmbedData {
+                                title: Some("queue".to_string()),
+                                description: [header]
+                                    .into_iter()
+                                    .chain(lines)
+                                    .collect::<Vec<_>>()
+                                    .join("\n")
+                                    .pipe(Some),
+                                color: Some(EMBED_COLOR),
+                                buttons: page_buttons("show-queue", page, pages),
+                                ..Default::default()
+                            }
+                        },
+                    )
+                    .map(|embed| reply_embed(embed, from))
                     .pipe(drop),
                 ShowHistory { page } => Connector::from_registry()
                     .send(GetHistoryStatus {
@@ -299,40 +989,838 @@ impl Handler<GuildCommandData> for GuildCommandProcesser {
                     .await
                     .expect("failed sending")
                     .map_err(|e| reply_err(e, from))
-                    .map(|HistoryStatus { history }| {
-                        let mut buf = String::new();
-                        history.into_iter().for_each(|(idx, info)| {
-                            write!(buf, "{}\n{}\n\n", idx, format_track_info(info)).unwrap()
-                        });
-                        buf
+                    .map(
+                        |HistoryStatus {
+                             history,
+                             page,
+                             pages,
+                             count,
+                         }| {
+                            let header = format!("page {} of {} / {} tracks", page, pages, count);
+
+                            let lines = history.into_iter().map(|(idx, info)| {
+                                format!("`{}` {}", idx, format_history_line(info))
+                            });
+
+                            EmbedData {
+                                title: Some("history".to_string()),
+                                description: [header]
+                                    .into_iter()
+                                    .chain(lines)
+                                    .collect::<Vec<_>>()
+                                    .join("\n")
+                                    .pipe(Some),
+                                color: Some(EMBED_COLOR),
+                                footer: Some("newest first".to_string()),
+                                buttons: page_buttons("show-history", page, pages),
+                                ..Default::default()
+                            }
+                        },
+                    )
+                    .map(|embed| reply_embed(embed, from))
+                    .pipe(drop),
+                PageSize { size: Some(size) } => Settings::from_registry()
+                    .send(SetSetting {
+                        guild,
+                        key: SettingKey::PageSize,
+                        value: size.to_string(),
                     })
-                    .map(|msg| reply(msg, from))
+                    .await
+                    .expect("failed sending")
+                    .map_err(|e| reply_err(e, from))
+                    .map(|s| reply(format!("page size: {}", s.page_size()), from))
                     .pipe(drop),
+                PageSize { size: None } => Settings::from_registry()
+                    .send(GetGuildSettings { guild })
+                    .await
+                    .expect("failed sending")
+                    .pipe(|s| reply(format!("page size: {}", s.page_size()), from)),
+                Alias { cmd } => Self::alias(guild, cmd, from).await,
+                Config { cmd } => match cmd {
+                    ConfigCommand::Get { key } => Settings::from_registry()
+                        .send(GetGuildSettings { guild })
+                        .await
+                        .expect("failed sending")
+                        .pipe(|s| reply(format_settings(&s, key), from)),
+                    ConfigCommand::Set { key, value } => Settings::from_registry()
+                        .send(SetSetting { guild, key, value })
+                        .await
+                        .expect("failed sending")
+                        .map_err(|e| reply_err(e, from))
+                        .map(|s| reply(format_settings(&s, Some(key)), from))
+                        .pipe(drop),
+                    ConfigCommand::Reset { key } => Settings::from_registry()
+                        .send(ResetSettings { guild, key })
+                        .await
+                        .expect("failed sending")
+                        .map_err(|e| reply_err(e, from))
+                        .map(|s| reply(format_settings(&s, key), from))
+                        .pipe(drop),
+                },
+
+                Repo { cmd } => match cmd {
+                    RepoCommand::Upload { name } => Repository::from_registry()
+                        .send(Upload {
+                            owner: user,
+                            guild: Some(guild),
+                            attachments,
+                            name,
+                        })
+                        .await
+                        .expect("failed sending")
+                        .map_err(|e| reply_err(e, from))
+                        .map(|tracks| {
+                            let mut buf = "uploaded:\n".to_string();
+                            tracks
+                                .into_iter()
+                                .for_each(|t| writeln!(buf, "{}", format_stored_track(t)).unwrap());
+                            buf
+                        })
+                        .map(|msg| reply(msg, from))
+                        .pipe(drop),
+                    RepoCommand::List => {
+                        let tracks = Repository::from_registry()
+                            .send(ListTracks {
+                                user,
+                                guild: Some(guild),
+                            })
+                            .await
+                            .expect("failed sending");
+
+                        if tracks.is_empty() {
+                            return reply("no tracks", from);
+                        }
+
+                        let mut buf = String::new();
+                        tracks
+                            .into_iter()
+                            .for_each(|t| writeln!(buf, "{}", format_stored_track(t)).unwrap());
+                        reply(buf, from)
+                    },
+                    RepoCommand::Enqueue { id } => Connector::from_registry()
+                        .try_send(ControlAction {
+                            kind: ControlActionKind::Enqueue {
+                                source: Source::Repository { id },
+                                user,
+                            },
+                            from,
+                            guild,
+                        })
+                        .expect("failed sending"),
+                    RepoCommand::Rename { id, name } => Repository::from_registry()
+                        .send(RenameTrack { id, user, name })
+                        .await
+                        .expect("failed sending")
+                        .map_err(|e| reply_err(e, from))
+                        .map(|msg| reply(msg, from))
+                        .pipe(drop),
+                    RepoCommand::Delete { id } => Repository::from_registry()
+                        .send(DeleteTrack { id, user })
+                        .await
+                        .expect("failed sending")
+                        .map_err(|e| reply_err(e, from))
+                        .map(|msg| reply(msg, from))
+                        .pipe(drop),
+                    RepoCommand::Visibility { id, visibility } => Repository::from_registry()
+                        .send(SetVisibility {
+                            id,
+                            user,
+                            visibility,
+                        })
+                        .await
+                        .expect("failed sending")
+                        .map_err(|e| reply_err(e, from))
+                        .map(|msg| reply(msg, from))
+                        .pipe(drop),
+                    RepoCommand::Share {
+                        id,
+                        user: target_user,
+                        guild: target_guild,
+                    } => Self::share(id, user, target_user, target_guild, true, from).await,
+                    RepoCommand::Unshare {
+                        id,
+                        user: target_user,
+                        guild: target_guild,
+                    } => Self::share(id, user, target_user, target_guild, false, from).await,
+                },
             }
         }
         .pipe(Box::pin)
     }
 }
+impl GuildCommandProcesser {
+    async fn alias(guild: u64, cmd: AliasCommand, from: MessageRef) {
+        let (name, expansion) = match cmd {
+            AliasCommand::List => {
+                let GuildSettings { aliases, .. } = Settings::from_registry()
+                    .send(GetGuildSettings { guild })
+                    .await
+                    .expect("failed sending");
+                return reply(format_aliases(&aliases), from);
+            },
+            AliasCommand::Set { name, command } => match parse_alias(&name, command) {
+                Ok(e) => (name, Some(e)),
+                Err(e) => return reply_err(e, from),
+            },
+            AliasCommand::Remove { name } => (name, None),
+        };
+
+        Settings::from_registry()
+            .send(SetAlias {
+                guild,
+                name,
+                expansion,
+            })
+            .await
+            .expect("failed sending")
+            .map_err(|e| reply_err(e, from))
+            .map(|s| reply(format_aliases(&s.aliases), from))
+            .pipe(drop)
+    }
+
+    async fn permission(guild: u64, cmd: PermissionCommand, from: MessageRef) {
+        let resolve = |query| async move {
+            Permissions::from_registry()
+                .send(ResolveRole { guild, query })
+                .await
+                .expect("failed sending")
+        };
+        let known = |command: String| {
+            let known = guild_command()
+                .get_subcommands()
+                .any(|c| c.get_name() == command);
+            match known {
+                true => Ok(command),
+                false => format!("unknown command: {}", command).pipe(Err),
+            }
+        };
+
+        let update: Result<_, String> = try {
+            use PermissionCommand::*;
+            match cmd {
+                Show => None,
+                DjRole {
+                    role: Some(role), ..
+                } => PermissionUpdate::DjRole(Some(resolve(role).await?)).pipe(Some),
+                DjRole { role: None, .. } => PermissionUpdate::DjRole(None).pipe(Some),
+                Allow { command, role } => PermissionUpdate::Allow {
+                    command: known(command)?,
+                    role: resolve(role).await?,
+                }
+                .pipe(Some),
+                Deny { command, role } => PermissionUpdate::Deny {
+                    command: known(command)?,
+                    role: resolve(role).await?,
+                }
+                .pipe(Some),
+                Clear { command } => PermissionUpdate::Clear { command }.pipe(Some),
+            }
+        };
+        let update = match update {
+            Ok(u) => u,
+            Err(e) => return reply_err(e, from),
+        };
+
+        Permissions::from_registry()
+            .send(UpdatePermissions { guild, update })
+            .await
+            .expect("failed sending")
+            .map_err(|e| reply_err(e, from))
+            .map(|s| reply(format_permissions(&s), from))
+            .pipe(drop)
+    }
+
+    async fn share(
+        id: u64,
+        user: u64,
+        target_user: Option<u64>,
+        target_guild: Option<u64>,
+        share: bool,
+        from: MessageRef,
+    ) {
+        let target = match (target_user, target_guild) {
+            (Some(u), None) => ShareTarget::User(u),
+            (None, Some(g)) => ShareTarget::Guild(g),
+            t => unreachable!("unexpected pattern: {:?}", t),
+        };
+
+        Repository::from_registry()
+            .send(ShareTrack {
+                id,
+                user,
+                target,
+                share,
+            })
+            .await
+            .expect("failed sending")
+            .map_err(|e| reply_err(e, from))
+            .map(|msg| reply(msg, from))
+            .pipe(drop)
+    }
+}
 impl Supervised for GuildCommandProcesser {}
 impl ArbiterService for GuildCommandProcesser {}
 
-fn format_track_status(
+pub struct PrivateCommandData {
+    cmd: PrivateCommand,
+    attachments: Vec<Attachment>,
+    from: MessageRef,
+    user: u64,
+}
+impl Message for PrivateCommandData {
+    type Result = ();
+}
+
+#[derive(Default)]
+pub struct PrivateCommandProcesser;
+impl Actor for PrivateCommandProcesser {
+    type Context = Context<Self>;
+}
+impl Handler<PrivateCommandData> for PrivateCommandProcesser {
+    type Result = ResponseFuture<()>;
+
+    fn handle(
+        &mut self,
+        PrivateCommandData {
+            cmd,
+            attachments,
+            from,
+            user,
+        }: PrivateCommandData,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        async move {
+            use PrivateCommand::*;
+            match cmd {
+                Help => reply(help(), from),
+                Version => reply(format!("vivara v{}", env!("CARGO_PKG_VERSION")), from),
+                Calls => {
+                    let CallsStatus { calls } = Connector::from_registry()
+                        .send(GetCallsStatus)
+                        .await
+                        .expect("failed sending");
+
+                    if calls.is_empty() {
+                        return reply("no calls", from);
+                    }
+
+                    let mut buf = String::new();
+                    calls
+                        .into_iter()
+                        .for_each(|(guild, channel)| match channel {
+                            Some(c) => writeln!(buf, "{}: <#{}>", guild, c).unwrap(),
+                            None => writeln!(buf, "{}: (disconnected)", guild).unwrap(),
+                        });
+                    reply(buf, from)
+                },
+                Guild { guild, cmd } => {
+                    let user_channel = VoiceStates::from_registry()
+                        .send(GetUserChannel { guild, user })
+                        .await
+                        .expect("failed sending");
+                    let user_channel = match user_channel {
+                        Some(c) => c,
+                        None =>
+                            return reply_err("you are not in a voice channel of the guild", from),
+                    };
+
+                    let CallsStatus { calls } = Connector::from_registry()
+                        .send(GetCallsStatus)
+                        .await
+                        .expect("failed sending");
+                    let bot_channel = calls
+                        .into_iter()
+                        .find(|(g, _)| *g == guild)
+                        .and_then(|(_, c)| c);
+                    match bot_channel {
+                        Some(c) if c == user_channel => (),
+                        Some(_) =>
+                            return reply_err("you are not in the voice channel of the bot", from),
+                        None => return reply_err("not in a voice channel of the guild", from),
+                    }
+
+                    GuildCommandProcesser::from_registry()
+                        .try_send(GuildCommandData {
+                            cmd,
+                            attachments,
+                            from,
+                            guild,
+                            user,
+                            roles: None,
+                        })
+                        .expect("failed sending")
+                },
+            }
+        }
+        .pipe(Box::pin)
+    }
+}
+impl Supervised for PrivateCommandProcesser {}
+impl ArbiterService for PrivateCommandProcesser {}
+
+fn help() -> String {
+    use clap::CommandFactory;
+
+    let mut guild = vec![];
+    GuildCommandParser::command()
+        .name(config().prefix.as_str())
+        .write_help(&mut guild)
+        .expect("failed writing help");
+
+    let mut private = vec![];
+    PrivateCommandParser::command()
+        .name(config().prefix.as_str())
+        .write_help(&mut private)
+        .expect("failed writing help");
+
+    format!(
+        "in guilds:\n```\n{}```\nin direct messages:\n```\n{}```",
+        String::from_utf8_lossy(&guild),
+        String::from_utf8_lossy(&private)
+    )
+}
+
+fn track_status_embed(
     TrackStatus {
+        data,
         mode,
         volume,
         position,
         total,
         loops,
+        block_loop,
     }: TrackStatus,
+) -> EmbedData {
+    let (title, duration, thumbnail) = match &data {
+        Some(d) => (d.title.clone(), d.duration, d.thumbnail.clone()),
+        None => (None, None, None),
+    };
+
+    let mut fields = vec![];
+    if let Some(TrackData {
+        source,
+        user,
+        artist,
+        source_url,
+        ..
+    }) = data
+    {
+        fields.push(EmbedField::inline(
+            "artist",
+            artist.unwrap_or_else(|| "unknown".to_string()),
+        ));
+        fields.push(EmbedField::inline("requested by", format!("<@{}>", user)));
+        fields.push(EmbedField::new(
+            "source",
+            source_url.unwrap_or_else(|| source.to_string()),
+        ));
+    }
+    fields.push(EmbedField::inline("mode", mode.to_string()));
+    fields.push(EmbedField::inline(
+        "volume",
+        format!("{}%", (volume * 100.0).round()),
+    ));
+    fields.push(EmbedField::inline("loop", loops.to_string()));
+    if let Some(BlockLoop { block, remaining }) = block_loop {
+        let remaining = match remaining {
+            Some(n) => format!("{} more times", n),
+            None => "Infinite".to_string(),
+        };
+        fields.push(EmbedField::inline(
+            "block loop",
+            format!("#{} - {}", block, remaining),
+        ));
+    }
+
+    EmbedData {
+        title: title.unwrap_or_else(|| "unknown".to_string()).pipe(Some),
+        description: format_progress(position, duration).pipe(Some),
+        fields,
+        color: Some(EMBED_COLOR),
+        footer: format!("total playing: {}", format_duration(total)).pipe(Some),
+        thumbnail,
+        buttons: vec![],
+    }
+}
+
+/// first, previous, next and last, as "{command}:{label}:{page}"
+fn page_buttons(command: &str, page: usize, pages: usize) -> Vec<ButtonData> {
+    [
+        ("first", 1),
+        ("prev", page.saturating_sub(1).max(1)),
+        ("next", (page + 1).min(pages)),
+        ("last", pages),
+    ]
+    .into_iter()
+    .map(|(label, to)| ButtonData {
+        custom_id: format!("{}:{}:{}", command, label, to),
+        label: label.to_string(),
+        disabled: to == page,
+    })
+    .collect()
+}
+
+/// arguments to run by a button of `page_buttons`
+pub fn page_button_args(custom_id: &str) -> Option<Vec<String>> {
+    let mut split = custom_id.split(':');
+    let (command, _, page) = (split.next()?, split.next()?, split.next()?);
+
+    if !PAGED_COMMANDS.contains(&command) || page.parse::<usize>().is_err() {
+        return None;
+    }
+
+    vec![command.to_string(), page.to_string()].pipe(Some)
+}
+
+/// e.g. "━━━━●────── 1:23 / 4:56"
+fn format_progress(position: Duration, duration: Option<Duration>) -> String {
+    let duration = match duration {
+        Some(d) if !d.is_zero() => d,
+        _ => return format_duration(position),
+    };
+
+    let ratio = (position.as_secs_f64() / duration.as_secs_f64()).clamp(0.0, 1.0);
+    let filled = (ratio * PROGRESS_WIDTH as f64).round() as usize;
+
+    format!(
+        "{}●{} {} / {}",
+        "━".repeat(filled),
+        "─".repeat(PROGRESS_WIDTH - filled),
+        format_duration(position),
+        format_duration(duration)
+    )
+}
+
+fn format_track_line(TrackStatus { data, position, .. }: TrackStatus) -> String {
+    let data = match data {
+        Some(d) => d,
+        None => return format!("unknown ({})", format_duration(position)),
+    };
+
+    let time = match (position.is_zero(), data.duration) {
+        (true, Some(d)) => format_duration(d),
+        (true, None) => "unknown".to_string(),
+        (false, d) => format_progress_short(position, d),
+    };
+
+    format!(
+        "{} ({}) <@{}>",
+        data.title.unwrap_or_else(|| data.source.to_string()),
+        time,
+        data.user
+    )
+}
+
+fn format_history_line(
+    TrackInfo {
+        data,
+        finished_at,
+        position,
+    }: TrackInfo,
 ) -> String {
+    let finished_at = finished_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
     format!(
-        "mode: {}\nvolume: {}\nposition: {}s\ntotal playing: {}s\nloop: {}",
+        "{} ({}) <@{}> <t:{}:R>",
+        data.title.unwrap_or_else(|| data.source.to_string()),
+        format_progress_short(position, data.duration),
+        data.user,
+        finished_at
+    )
+}
+
+/// e.g. "1:23 / 4:56"
+fn format_progress_short(position: Duration, duration: Option<Duration>) -> String {
+    match duration {
+        Some(d) => format!("{} / {}", format_duration(position), format_duration(d)),
+        None => format_duration(position),
+    }
+}
+
+pub fn format_track_status(
+    TrackStatus {
+        data,
         mode,
         volume,
-        position.as_secs_f32(),
-        total.as_secs_f32(),
+        position,
+        total,
+        loops,
+        block_loop,
+    }: TrackStatus,
+) -> String {
+    let mut buf = String::new();
+
+    if let Some(data) = data {
+        writeln!(buf, "{}", format_track_data(data)).unwrap();
+    }
+
+    write!(
+        buf,
+        "mode: {}\nvolume: {}\nposition: {}\ntotal playing: {}\nloop: {}",
+        mode,
+        volume,
+        format_duration(position),
+        format_duration(total),
         loops
     )
+    .unwrap();
+
+    if let Some(BlockLoop { block, remaining }) = block_loop {
+        match remaining {
+            Some(n) => write!(buf, "\nblock loop: #{} - {} more times", block, n),
+            None => write!(buf, "\nblock loop: #{} - Infinite", block),
+        }
+        .unwrap();
+    }
+
+    buf
 }
 
-fn format_track_info(TrackInfo { url }: TrackInfo) -> String { format!("url: {}", url) }
+fn format_track_data(
+    TrackData {
+        source,
+        user,
+        title,
+        artist,
+        duration,
+        thumbnail: _,
+        source_url,
+        channel: _,
+    }: TrackData,
+) -> String {
+    let source = match source_url {
+        Some(url) => format!("<{}>", url),
+        None => source.to_string(),
+    };
+
+    format!(
+        "title: {}\nartist: {}\nduration: {}\nsource: {}\nrequested by: <@{}>",
+        title.as_deref().unwrap_or("unknown"),
+        artist.as_deref().unwrap_or("unknown"),
+        duration.map_or_else(|| "unknown".to_string(), format_duration),
+        source,
+        user
+    )
+}
+
+fn format_stored_track(
+    StoredTrack {
+        id,
+        name,
+        owner,
+        guild: _,
+        file: _,
+        uploaded_at,
+        visibility,
+        shared_users: _,
+        shared_guilds: _,
+    }: StoredTrack,
+) -> String {
+    let uploaded_at = uploaded_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    format!(
+        "#{}: {} (by <@{}>, <t:{}:R>, {})",
+        id, name, owner, uploaded_at, visibility
+    )
+}
+
+fn format_policy(
+    presence::Policy {
+        leave_empty,
+        leave_idle,
+        pause_empty,
+    }: presence::Policy,
+) -> String {
+    format!(
+        "leave-empty: {}\nleave-idle: {}\npause-empty: {}",
+        leave_empty, leave_idle, pause_empty
+    )
+}
+
+/// validates the alias, and returns the words replacing it
+fn parse_alias(name: &str, command: Vec<String>) -> Result<Vec<String>, String> {
+    if name.is_empty() || name.contains(char::is_whitespace) || name.starts_with('-') {
+        return format!("illegal alias name: {}", name).pipe(Err);
+    }
+    if is_builtin_command(name) {
+        return format!("already a command: {}", name).pipe(Err);
+    }
+
+    let mut command = match command.as_slice() {
+        // options of application commands are not split
+        [one] => shell_words::split(one).map_err(|e| e.to_string())?,
+        _ => command,
+    };
+    if command.first().is_some_and(|w| w == "=") {
+        command.remove(0);
+    }
+
+    match command.first() {
+        Some(c) if is_builtin_command(c) => Ok(command),
+        Some(c) => format!("not a command: {}", c).pipe(Err),
+        None => "specify a command".to_string().pipe(Err),
+    }
+}
+
+fn format_aliases(aliases: &BTreeMap<String, Vec<String>>) -> String {
+    if aliases.is_empty() {
+        return "no aliases".to_string();
+    }
+
+    aliases
+        .iter()
+        .map(|(name, expansion)| format!("{} = {}", name, shell_words::join(expansion)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_settings(settings: &GuildSettings, key: Option<SettingKey>) -> String {
+    let keys = match key {
+        Some(k) => vec![k],
+        None => SettingKey::value_variants().to_vec(),
+    };
+
+    keys.into_iter()
+        .map(|k| match settings.get(k) {
+            (value, true) => format!("{}: {}", k.name(), value),
+            (value, false) => format!("{}: {} (default)", k.name(), value),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_permissions(status: &PermissionsStatus) -> String {
+    let PermissionSettings { dj_role, rules } = &status.settings;
+
+    let mut buf = match dj_role {
+        Some(r) => format!("dj-role: {}\n", status.role_name(*r)),
+        None => "dj-role: off\n".to_string(),
+    };
+
+    let roles = |roles: &BTreeSet<u64>| {
+        roles
+            .iter()
+            .map(|r| status.role_name(*r))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    for (command, Rule { allow, deny }) in rules {
+        if !allow.is_empty() {
+            writeln!(buf, "{}: allowed for {}", command, roles(allow)).unwrap();
+        }
+        if !deny.is_empty() {
+            writeln!(buf, "{}: denied for {}", command, roles(deny)).unwrap();
+        }
+    }
+
+    buf
+}
+
+fn format_search_result(
+    SearchResult {
+        title,
+        url,
+        duration,
+        uploader,
+    }: SearchResult,
+) -> String {
+    let mut buf = title;
+    match (duration, uploader) {
+        (Some(d), Some(u)) => write!(buf, " ({}, {})", format_duration(d), u),
+        (Some(d), None) => write!(buf, " ({})", format_duration(d)),
+        (None, Some(u)) => write!(buf, " ({})", u),
+        (None, None) => Ok(()),
+    }
+    .unwrap();
+    write!(buf, " <{}>", url).unwrap();
+
+    buf
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+    match h {
+        0 => format!("{}:{:02}", m, s),
+        _ => format!("{}:{:02}:{:02}", h, m, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(s: &str) -> Vec<String> { shell_words::split(s).unwrap() }
+
+    #[test]
+    fn strip_invocation_by_prefix_mention_or_channel() {
+        let prefixes = ["*v".to_string(), "!v".to_string()];
+        let aliases = BTreeMap::new();
+
+        assert_eq!(
+            strip_invocation(words("!v show-queue"), &prefixes, 1, false, &aliases),
+            Some(words("!v show-queue"))
+        );
+        assert_eq!(
+            strip_invocation(words("<@!1> stop"), &prefixes, 1, false, &aliases),
+            Some(words("<@!1> stop"))
+        );
+        assert_eq!(
+            strip_invocation(words("<@2> stop"), &prefixes, 1, false, &aliases),
+            None
+        );
+        assert_eq!(
+            strip_invocation(words("stop"), &prefixes, 1, false, &aliases),
+            None
+        );
+
+        assert_eq!(
+            strip_invocation(words("stop"), &prefixes, 1, true, &aliases),
+            Some(vec![String::new(), "stop".to_string()])
+        );
+        assert_eq!(
+            strip_invocation(words("stop it"), &prefixes, 1, true, &aliases).map(|w| w.len()),
+            Some(3)
+        );
+        assert_eq!(
+            strip_invocation(words("hello"), &prefixes, 1, true, &aliases),
+            None
+        );
+        assert_eq!(strip_invocation(vec![], &prefixes, 1, true, &aliases), None);
+        assert_eq!(
+            strip_invocation(words("np"), &prefixes, 1, true, &aliases),
+            Some(words("'' np"))
+        );
+    }
+
+    #[test]
+    fn expand_aliases() {
+        let expansion = parse_alias("lofi", words("= enqueue https://example.com")).unwrap();
+        assert_eq!(expansion, words("enqueue https://example.com"));
+        assert_eq!(
+            parse_alias("vol", vec!["volume-current 0.5".to_string()]),
+            Ok(words("volume-current 0.5"))
+        );
+        assert!(parse_alias("q", words("enqueue x")).is_err());
+        assert!(parse_alias("lofi", words("lofi")).is_err());
+
+        let mut aliases = BTreeMap::new();
+        aliases.insert("lofi".to_string(), expansion);
+        assert_eq!(
+            expand_alias(words("*v lofi --items 1..3"), &aliases),
+            words("*v enqueue https://example.com --items 1..3")
+        );
+        assert_eq!(expand_alias(words("*v q"), &aliases), words("*v q"));
+
+        let GuildCommandParser { cmd } =
+            GuildCommandParser::try_parse_from(words("*v np")).unwrap();
+        assert_eq!(cmd.name(), "show-current");
+    }
+}
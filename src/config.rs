@@ -0,0 +1,178 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use twilight_gateway::Intents;
+
+use crate::util::Pipe;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// global settings, loaded from "$VIVARA_CONFIG" (default "vivara.toml")
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub token: TokenSource,
+    /// default of guilds, and the only one in direct messages
+    pub prefix: String,
+    /// "message-content" is privileged and left out by default, opt in for
+    /// text commands by a prefix or in "music-channel"
+    pub intents: Vec<String>,
+    pub log_level: String,
+    /// volume of joined guilds, same as the argument of "volume"
+    pub default_volume: f32,
+    pub page_size: usize,
+    pub limits: Limits,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            token: TokenSource::Env("DISCORD_BOT_TOKEN".to_string()),
+            prefix: "*v".to_string(),
+            intents: [
+                "guilds",
+                "guild-voice-states",
+                "guild-messages",
+                "direct-messages",
+            ]
+            .map(String::from)
+            .to_vec(),
+            log_level: "info".to_string(),
+            default_volume: 1.0,
+            page_size: 10,
+            limits: Limits::default(),
+        }
+    }
+}
+impl Config {
+    pub fn token(&self) -> Result<String, String> {
+        match &self.token {
+            TokenSource::Env(var) => std::env::var(var).map_err(|e| format!("{}: {}", var, e))?,
+            TokenSource::File(path) => fs::read_to_string(path)
+                .map_err(|e| format!("{}: {}", path, e))?
+                .trim()
+                .to_string(),
+            TokenSource::Value(token) => token.clone(),
+        }
+        .pipe(Ok)
+    }
+
+    pub fn intents(&self) -> Result<Intents, String> {
+        self.intents
+            .iter()
+            .map(|name| match name.as_str() {
+                "guilds" => Ok(Intents::GUILDS),
+                "guild-members" => Ok(Intents::GUILD_MEMBERS),
+                "guild-voice-states" => Ok(Intents::GUILD_VOICE_STATES),
+                "guild-messages" => Ok(Intents::GUILD_MESSAGES),
+                "direct-messages" => Ok(Intents::DIRECT_MESSAGES),
+                "message-content" => Ok(Intents::MESSAGE_CONTENT),
+                _ => format!("unknown intent: {}", name).pipe(Err),
+            })
+            .try_fold(Intents::empty(), |acc, i| i.map(|i| acc | i))
+    }
+
+    pub fn log_level(&self) -> Result<tracing::Level, String> {
+        self.log_level
+            .parse()
+            .map_err(|_| format!("unknown log level: {}", self.log_level))
+    }
+}
+
+/// where to read the token from, e.g. `token = { env = "DISCORD_BOT_TOKEN" }`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenSource {
+    /// name of the environment variable
+    Env(String),
+    /// path of the file
+    File(String),
+    Value(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Limits {
+    /// entries of a playlist to enqueue
+    pub playlist: usize,
+    pub search: usize,
+    /// upper bound of "page-size"
+    pub max_page_size: usize,
+    /// finished tracks kept per guild
+    pub history: usize,
+    /// user-defined aliases per guild
+    pub aliases: usize,
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            playlist: 50,
+            search: 10,
+            max_page_size: 25,
+            history: 100,
+            aliases: 50,
+        }
+    }
+}
+
+/// defaults until loaded, or if failed loading
+pub fn config() -> &'static Config { CONFIG.get_or_init(Config::default) }
+
+/// loads once, before anything reads the config
+pub fn load() -> Result<(), String> {
+    let path = std::env::var("VIVARA_CONFIG").unwrap_or_else(|_| "vivara.toml".to_string());
+
+    let config = match fs::read_to_string(&path) {
+        Ok(s) => parse(&s).map_err(|e| format!("{}: {}", path, e))?,
+        Err(e) if e.kind() == ErrorKind::NotFound => Config::default(),
+        Err(e) => return format!("{}: {}", path, e).pipe(Err),
+    };
+
+    CONFIG.set(config).map_err(|_| "already loaded".to_string())
+}
+
+fn parse(s: &str) -> Result<Config, String> {
+    let config: Config = toml::from_str(s).map_err(|e| e.to_string())?;
+
+    config.intents()?;
+    config.log_level()?;
+    if config.page_size == 0 || config.page_size > config.limits.max_page_size {
+        return format!("page-size must be in 1..={}", config.limits.max_page_size).pipe(Err);
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config = parse(
+            r#"
+            token = { file = "token.txt" }
+            prefix = "!v"
+            log-level = "debug"
+
+            [limits]
+            playlist = 10
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(config.token, TokenSource::File(ref p) if p == "token.txt"));
+        assert_eq!(config.prefix, "!v");
+        assert_eq!(config.limits.playlist, 10);
+        assert_eq!(config.limits.search, 10);
+        assert_eq!(config.page_size, 10);
+        assert!(!config.intents().unwrap().contains(Intents::MESSAGE_CONTENT));
+
+        assert!(parse("intents = [\"presences\"]").is_err());
+        assert!(parse("page-size = 100").is_err());
+        assert!(parse("page-size = 100\n[limits]\nmax-page-size = 100").is_ok());
+        assert!(parse("[limits]\npage-size = 25").is_err());
+        assert!(parse("unknown = 1").is_err());
+    }
+}
@@ -1,42 +1,150 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use core::fmt::{Display, Write};
-use core::ops::Bound;
+use core::ops::{Bound, Range};
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::time::Duration;
+use std::collections::HashSet;
+use std::time::SystemTime;
 
 use actix::prelude::{
-    Actor, ArbiterService, Context, ContextFutureSpawner, Handler, Message, ResponseFuture,
-    Supervised, WrapFuture,
+    Actor, ArbiterService, AsyncContext, Context, ContextFutureSpawner, Handler, Message,
+    ResponseFuture, Supervised, WrapFuture,
 };
+use async_trait::async_trait;
 use dashmap::DashMap;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
+use serde::{Deserialize, Serialize};
 use songbird::error::JoinError;
 use songbird::id::{ChannelId, GuildId};
-use songbird::input::Restartable;
-use songbird::tracks::{LoopState, PlayMode, TrackHandle, TrackState};
-use songbird::{create_player, Call, Songbird};
+use songbird::input::{Input, Metadata};
+use songbird::tracks::{LoopState, PlayMode, Track, TrackHandle, TrackState};
+use songbird::typemap::TypeMapKey;
+use songbird::{create_player, Call, Event, EventContext, EventHandler, Songbird, TrackEvent};
 use tokio::sync::Mutex;
 
+use crate::announce::{Announcer, TrackNotice, TrackNoticeKind};
+use crate::config::config;
 use crate::gateway::MessageRef;
+use crate::permission::Access;
+use crate::settings::{GetGuildSettings, GuildSettings, Settings};
+use crate::source::{self, Source};
+use crate::storage::{GuildSnapshot, QueuedTrack, Storage};
+use crate::timespec::TimeSpec;
 use crate::util::{reply, reply_err, Pipe};
+use crate::voice::{GetListeners, GetUserChannel, VoiceStates};
 
 type StringResult = Result<String, String>;
 
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(30);
+const STUCK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// played time to be regarded as started
+const START_DELAY: Duration = Duration::from_millis(100);
+const ERROR_MARGIN: Duration = Duration::from_secs(5);
+
+static NEXT_BLOCK: AtomicU64 = AtomicU64::new(0);
+
+/// returns the number of pages and the range of items in the page (1-based)
+fn paginate(len: usize, page: usize, page_size: usize) -> Result<(usize, Range<usize>), String> {
+    if page == 0 {
+        return "cannot specify page under 1".to_string().pipe(Err);
+    }
+
+    // an empty page if no items
+    let pages = len.div_ceil(page_size).max(1);
+    if page > pages {
+        return format!("out of bounds (only {} pages)", pages).pipe(Err);
+    }
+
+    let start = page_size * (page - 1);
+    let end = (start + page_size).min(len);
+
+    (pages, start..end).pipe(Ok)
+}
+
+/// numbers history newest first, as shown by "history"
+fn newest_first<T>(history: &[T]) -> impl Iterator<Item = (usize, &T)> {
+    history.iter().rev().enumerate()
+}
+
+/// picks tracks by the numbers of "history", returning them oldest first
+fn pick_history<T>(history: &[T], target: HistoryTarget) -> Result<Vec<&T>, String> {
+    let (start, end) = match target {
+        HistoryTarget::Index(i) => (Bound::Included(i), Bound::Included(i)),
+        HistoryTarget::Range(range) => range,
+    };
+
+    let numbered = newest_first(history).collect::<Vec<_>>();
+    let picked = match numbered.get((start, end)) {
+        Some(p) if !p.is_empty() => p,
+        _ => return "out of bounds".to_string().pipe(Err),
+    };
+
+    picked
+        .iter()
+        .rev()
+        .map(|(_, t)| *t)
+        .collect::<Vec<_>>()
+        .pipe(Ok)
+}
+
+/// how "skip" skips the tracks
+#[derive(Debug, PartialEq, Eq)]
+enum SkipBy {
+    Instant,
+    /// by votes of the percent of listeners
+    Vote(u8),
+}
+
+/// decides how to skip `count` tracks, with the "vote-skip" percent as
+/// `threshold`
+fn skip_by(
+    access: Access,
+    owned: bool,
+    threshold: Option<u8>,
+    count: usize,
+) -> Result<SkipBy, String> {
+    match (access, threshold) {
+        (Access::Privileged, _) => Ok(SkipBy::Instant),
+        _ if owned => Ok(SkipBy::Instant),
+        (Access::All, None) => Ok(SkipBy::Instant),
+        (_, None) => "denied: you can only skip tracks you enqueued"
+            .to_string()
+            .pipe(Err),
+        (_, Some(_)) if count > 1 => "votes only skip the current track".to_string().pipe(Err),
+        (_, Some(percent)) => Ok(SkipBy::Vote(percent)),
+    }
+}
+
+/// votes to skip, `percent` of `listeners` rounded up (at least one)
+fn required_votes(listeners: usize, percent: u8) -> usize {
+    (listeners * percent as usize).div_ceil(100).max(1)
+}
+
 pub struct Connector {
     songbird: Arc<Songbird>,
+    storage: Arc<dyn Storage>,
     default_volumes: Arc<DashMap<u64, f32>>,
     history: Arc<DashMap<u64, Vec<TrackInfo>>>,
 }
 impl Connector {
-    pub fn new(songbird: Arc<Songbird>) -> Self {
+    pub fn new(songbird: Arc<Songbird>, storage: Arc<dyn Storage>) -> Self {
         Self {
             songbird,
+            storage,
             default_volumes: DashMap::new().pipe(Arc::new),
             history: DashMap::new().pipe(Arc::new),
         }
     }
 
+    async fn settings(guild: u64) -> GuildSettings {
+        Settings::from_registry()
+            .send(GetGuildSettings { guild })
+            .await
+            .expect("failed sending")
+    }
+
     fn try_get_call(songbird: &Arc<Songbird>, guild: GuildId) -> Result<Arc<Mutex<Call>>, String> {
         match songbird.get(guild) {
             Some(call) => Ok(call),
@@ -56,6 +164,19 @@ impl Default for Connector {
 }
 impl Actor for Connector {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(SNAPSHOT_INTERVAL, |this, ctx| {
+            let songbird = this.songbird.clone();
+            let storage = this.storage.clone();
+            let default_volumes = this.default_volumes.clone();
+            let history = this.history.clone();
+
+            Self::save_snapshots(songbird, storage, default_volumes, history)
+                .into_actor(this)
+                .spawn(ctx);
+        });
+    }
 }
 impl Handler<CallAction> for Connector {
     type Result = ();
@@ -66,17 +187,25 @@ impl Handler<CallAction> for Connector {
         ctx: &mut Self::Context,
     ) -> Self::Result {
         let songbird = self.songbird.clone();
+        let storage = self.storage.clone();
         let default_volumes = self.default_volumes.clone();
+        let history = self.history.clone();
 
         async move {
             use CallActionKind::*;
             let result = match kind {
                 Join { channel } => Self::join(songbird, default_volumes, guild, channel).await,
-                Leave => Self::leave(songbird, default_volumes, guild).await,
+                Leave => Self::leave(songbird, storage, default_volumes, guild).await,
                 Slide { from, to } => Self::slide(songbird, guild, from, to).await,
-                Drop { kind } => Self::drop(songbird, guild, kind).await,
-                Fix => Self::fix(songbird, guild).await,
+                Drop { kind, requester } => Self::drop(songbird, guild, kind, requester).await,
+                Fix { user } =>
+                    Self::fix(songbird, storage, default_volumes, history, guild, user).await,
                 Stop => Self::stop(songbird, guild).await,
+                Skip {
+                    count,
+                    user,
+                    access,
+                } => Self::skip(songbird, guild, count, user, access).await,
             };
 
             match result {
@@ -118,25 +247,63 @@ impl Connector {
             return Err(e.to_string());
         }
 
-        if default_volumes.insert(guild.0, 1.0).is_some() {
-            unreachable!("must empty value");
-        }
+        // kept if rejoining after disconnected
+        let volume = Self::settings(guild.0).await.default_volume();
+        default_volumes.entry(guild.0).or_insert(volume);
 
         Ok("joined".to_string())
     }
 
+    /// joins the voice channel of `user` if not connected yet
+    async fn join_if_needed(
+        songbird: &Arc<Songbird>,
+        default_volumes: &Arc<DashMap<u64, f32>>,
+        guild: GuildId,
+        user: u64,
+    ) -> Result<Arc<Mutex<Call>>, String> {
+        let connected: Option<Arc<Mutex<Call>>> = try {
+            let call = songbird.get(guild)?;
+            call.lock().await.current_channel()?;
+            call
+        };
+        if let Some(call) = connected {
+            return Ok(call);
+        }
+
+        let channel = VoiceStates::from_registry()
+            .send(GetUserChannel {
+                guild: guild.0,
+                user,
+            })
+            .await
+            .expect("failed sending")
+            .ok_or_else(|| "join a voice channel first".to_string())?;
+
+        Self::_join(
+            songbird.clone(),
+            default_volumes.clone(),
+            guild,
+            channel.into(),
+        )
+        .await?;
+
+        Self::try_get_call(songbird, guild)
+    }
+
     async fn leave(
         songbird: Arc<Songbird>,
+        storage: Arc<dyn Storage>,
         default_volumes: Arc<DashMap<u64, f32>>,
         guild: impl Into<GuildId>,
     ) -> StringResult {
         let guild = guild.into();
 
-        Self::_leave(songbird, default_volumes, guild).await
+        Self::_leave(songbird, storage, default_volumes, guild).await
     }
 
     async fn _leave(
         songbird: Arc<Songbird>,
+        storage: Arc<dyn Storage>,
         default_volumes: Arc<DashMap<u64, f32>>,
         guild: GuildId,
     ) -> StringResult {
@@ -148,6 +315,10 @@ impl Connector {
             unreachable!("must remove value");
         }
 
+        if let Err(e) = storage.remove(guild.0) {
+            tracing::warn!("failed removing snapshot of {}: {}", guild.0, e);
+        }
+
         Ok("leaved".to_string())
     }
 
@@ -194,13 +365,19 @@ impl Connector {
         songbird: Arc<Songbird>,
         guild: impl Into<GuildId>,
         kind: DropKind,
+        requester: Option<u64>,
     ) -> StringResult {
         let guild = guild.into();
 
-        Self::_drop(songbird, guild, kind).await
+        Self::_drop(songbird, guild, kind, requester).await
     }
 
-    async fn _drop(songbird: Arc<Songbird>, guild: GuildId, kind: DropKind) -> StringResult {
+    async fn _drop(
+        songbird: Arc<Songbird>,
+        guild: GuildId,
+        kind: DropKind,
+        requester: Option<u64>,
+    ) -> StringResult {
         enum Either<L, R> {
             Left(L),
             Right(R),
@@ -222,6 +399,43 @@ impl Connector {
 
         let call = Self::try_get_call(&songbird, guild)?;
 
+        // (start, uuids) of checked tracks, since the queue may move until dropping
+        let checked = match requester {
+            Some(user) => {
+                let handles = call.lock().await.queue().current_queue();
+                let (start, targets) = match &kind {
+                    DropKind::Index(index) => (*index, handles.get(*index..=*index)),
+                    DropKind::Range(range) => {
+                        let start = match range.0 {
+                            Bound::Included(s) => s,
+                            Bound::Excluded(s) => s + 1,
+                            Bound::Unbounded => 0,
+                        };
+                        (start, handles.get(*range))
+                    },
+                };
+                let targets = match targets {
+                    Some(t) => t,
+                    None => return "out of bounds".to_string().pipe(Err),
+                };
+
+                for handle in targets {
+                    let owned = match handle.typemap().read().await.get::<TrackData>() {
+                        Some(data) => data.user == user,
+                        None => false,
+                    };
+                    if !owned {
+                        return "denied: you can only drop tracks you enqueued"
+                            .to_string()
+                            .pipe(Err);
+                    }
+                }
+
+                Some((start, targets.iter().map(|h| h.uuid()).collect::<Vec<_>>()))
+            },
+            None => None,
+        };
+
         use DropKind::*;
         let func = match kind {
             Index(index) =>
@@ -245,23 +459,246 @@ impl Connector {
             .pipe(Either::Right),
         };
 
-        let result = call.lock().await.queue().modify_queue(func);
+        let result = call.lock().await.queue().modify_queue(|deq| {
+            if let Some((start, uuids)) = checked {
+                let unchanged = deq
+                    .iter()
+                    .skip(start)
+                    .take(uuids.len())
+                    .map(|q| q.uuid())
+                    .eq(uuids);
+                if !unchanged {
+                    return None;
+                }
+            }
+
+            func(deq).pipe(Some)
+        });
 
         match result {
-            true => "dropped".to_string().pipe(Ok),
-            false => "out of bounds".to_string().pipe(Err),
+            Some(true) => "dropped".to_string().pipe(Ok),
+            Some(false) => "out of bounds".to_string().pipe(Err),
+            None => "queue changed, try again".to_string().pipe(Err),
         }
     }
 
-    async fn fix(songbird: Arc<Songbird>, guild: impl Into<GuildId>) -> StringResult {
+    async fn fix(
+        songbird: Arc<Songbird>,
+        storage: Arc<dyn Storage>,
+        default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: impl Into<GuildId>,
+        user: u64,
+    ) -> StringResult {
         let guild = guild.into();
 
-        Self::_fix(songbird, guild).await
+        Self::_fix(songbird, storage, default_volumes, history, guild, user).await
+    }
+
+    /// reconnects to the current channel (or the last known one if
+    /// disconnected), and recreates the current track from its source (at the
+    /// last known position), keeping the rest of the queue
+    async fn _fix(
+        songbird: Arc<Songbird>,
+        storage: Arc<dyn Storage>,
+        default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: GuildId,
+        user: u64,
+    ) -> StringResult {
+        let call = match songbird.get(guild) {
+            Some(c) => c,
+            None => {
+                let channel = Self::last_channel(&storage, guild, user).await?;
+                Self::_join(songbird, default_volumes, guild, channel).await?;
+
+                return format!("channel: <#{}>\nconnection: none\nrejoined", channel.0).pipe(Ok);
+            },
+        };
+
+        let (channel, connected, current) = {
+            let guard = call.lock().await;
+            (
+                guard.current_channel(),
+                guard.current_connection().is_some(),
+                guard.queue().current(),
+            )
+        };
+
+        let mut buf = String::new();
+        let (channel, disconnected) = match channel {
+            Some(c) => (c, false),
+            None => (Self::last_channel(&storage, guild, user).await?, true),
+        };
+        writeln!(buf, "channel: <#{}>", channel.0).unwrap();
+        match (disconnected, connected) {
+            (true, _) => writeln!(buf, "connection: disconnected").unwrap(),
+            (false, true) => writeln!(buf, "connection: established").unwrap(),
+            (false, false) => writeln!(buf, "connection: lost").unwrap(),
+        }
+
+        let state = match &current {
+            Some(handle) => Some(Self::diagnose_track(handle).await),
+            None => None,
+        };
+        match &state {
+            Some(Ok((_, true))) => writeln!(buf, "current track: stuck").unwrap(),
+            Some(Ok((state, false))) =>
+                writeln!(buf, "current track: {:?}", state.playing).unwrap(),
+            Some(Err(e)) => writeln!(buf, "current track: {}", e).unwrap(),
+            None => writeln!(buf, "current track: none").unwrap(),
+        }
+
+        // driver keeps tracks on disconnecting, so the queue survives this
+        let join = {
+            let mut guard = call.lock().await;
+            if !disconnected {
+                guard.leave().await.map_err(|e| e.to_string())?;
+            }
+            guard.join(channel).await.map_err(|e| e.to_string())?
+        };
+        join.await.map_err(|e| e.to_string())?;
+        writeln!(buf, "reconnected").unwrap();
+
+        let handle = match current {
+            Some(h) => h,
+            None => return buf.pipe(Ok),
+        };
+
+        // removing data to stop recording the broken track into history
+        let data = match handle.typemap().write().await.remove::<TrackData>() {
+            Some(d) => d,
+            None => {
+                write!(buf, "current track has no source, left as is").unwrap();
+                return buf.pipe(Ok);
+            },
+        };
+
+        let default_volume = *default_volumes.get(&guild.0).expect("must get value");
+        let (position, volume, loops) = match state {
+            Some(Ok((s, _))) => (s.position, s.volume, s.loops),
+            _ => (Duration::ZERO, default_volume, LoopState::Finite(0)),
+        };
+        let block_loop = handle
+            .typemap()
+            .read()
+            .await
+            .get::<BlockLoop>()
+            .copied()
+            .flatten();
+
+        let created = Self::create_track(
+            history.clone(),
+            guild,
+            data.source.clone(),
+            data.user,
+            data.channel,
+            volume,
+        )
+        .await;
+        let (track, recreated) = match created {
+            Ok(o) => o,
+            Err(e) => {
+                handle.typemap().write().await.insert::<TrackData>(data);
+                return format!("{}failed recreating current track: {}", buf, e).pipe(Err);
+            },
+        };
+
+        if !position.is_zero() {
+            recreated.seek_time(position).map_err(|e| e.to_string())?;
+        }
+
+        match loops {
+            LoopState::Finite(0) => Ok(()),
+            LoopState::Finite(n) => recreated.loop_for(n),
+            LoopState::Infinite => recreated.enable_loop(),
+        }
+        .map_err(|e| e.to_string())?;
+        if let Some(mark) = block_loop {
+            recreated
+                .typemap()
+                .write()
+                .await
+                .insert::<BlockLoop>(Some(mark));
+            let requeuer = LoopRequeuer {
+                songbird: songbird.clone(),
+                history: history.clone(),
+                guild,
+            };
+            recreated
+                .add_event(Event::Track(TrackEvent::End), requeuer)
+                .map_err(|e| e.to_string())?;
+        }
+
+        let mut guard = call.lock().await;
+        guard.enqueue(track);
+
+        let queue = guard.queue();
+        queue.modify_queue(|deq| {
+            let recreated = deq.pop_back().expect("must have enqueued track");
+            deq.insert(1, recreated);
+        });
+
+        // if the broken track cannot be stopped, queue will not advance by itself
+        if queue.skip().is_err() {
+            queue.modify_queue(|deq| deq.pop_front());
+            queue.resume().map_err(|e| e.to_string())?;
+        }
+
+        write!(
+            buf,
+            "recreated current track at {}",
+            TimeSpec::Absolute(position)
+        )
+        .unwrap();
+
+        buf.pipe(Ok)
+    }
+
+    /// channel in the snapshot, or the one of `user`
+    async fn last_channel(
+        storage: &Arc<dyn Storage>,
+        guild: GuildId,
+        user: u64,
+    ) -> Result<ChannelId, String> {
+        let saved = match storage.load() {
+            Ok(snapshots) => snapshots
+                .into_iter()
+                .find(|s| s.guild == guild.0)
+                .and_then(|s| s.channel),
+            Err(e) => {
+                tracing::warn!("failed loading snapshots: {}", e);
+                None
+            },
+        };
+        if let Some(channel) = saved {
+            return ChannelId::from(channel).pipe(Ok);
+        }
+
+        VoiceStates::from_registry()
+            .send(GetUserChannel {
+                guild: guild.0,
+                user,
+            })
+            .await
+            .expect("failed sending")
+            .map(ChannelId::from)
+            .ok_or_else(|| "not in a voice channel, join a voice channel first".to_string())
     }
 
-    async fn _fix(_: Arc<Songbird>, _: GuildId) -> StringResult {
-        // TODO
-        "no operated".to_string().pipe(Ok)
+    /// returns state and whether it is stuck (playing but not advancing)
+    async fn diagnose_track(handle: &TrackHandle) -> Result<(TrackState, bool), String> {
+        let before = handle.get_info().await.map_err(|e| e.to_string())?;
+        if before.playing != PlayMode::Play {
+            return (before, false).pipe(Ok);
+        }
+
+        actix::clock::sleep(STUCK_CHECK_INTERVAL).await;
+
+        let after = handle.get_info().await.map_err(|e| e.to_string())?;
+        let stuck = after.playing == PlayMode::Play && after.position == before.position;
+
+        (after, stuck).pipe(Ok)
     }
 
     async fn stop(songbird: Arc<Songbird>, guild: impl Into<GuildId>) -> StringResult {
@@ -277,6 +714,102 @@ impl Connector {
 
         Ok("stopped".to_string())
     }
+
+    async fn skip(
+        songbird: Arc<Songbird>,
+        guild: impl Into<GuildId>,
+        count: usize,
+        user: u64,
+        access: Access,
+    ) -> StringResult {
+        let guild = guild.into();
+
+        Self::_skip(songbird, guild, count, user, access).await
+    }
+
+    async fn _skip(
+        songbird: Arc<Songbird>,
+        guild: GuildId,
+        count: usize,
+        user: u64,
+        access: Access,
+    ) -> StringResult {
+        let call = Self::try_get_call(&songbird, guild)?;
+        let (channel, handles) = {
+            let guard = call.lock().await;
+            (guard.current_channel(), guard.queue().current_queue())
+        };
+
+        if count == 0 || count > handles.len() {
+            return "out of bounds".to_string().pipe(Err);
+        }
+
+        let mut owned = true;
+        for handle in &handles[..count] {
+            owned &= match handle.typemap().read().await.get::<TrackData>() {
+                Some(data) => data.user == user,
+                None => false,
+            };
+        }
+
+        let threshold = Self::settings(guild.0).await.vote_skip;
+        if let SkipBy::Vote(percent) = skip_by(access, owned, threshold, count)? {
+            let channel = channel.ok_or_else(|| "not connected".to_string())?.0;
+            let voter = VoiceStates::from_registry()
+                .send(GetUserChannel {
+                    guild: guild.0,
+                    user,
+                })
+                .await
+                .expect("failed sending");
+            if voter != Some(channel) {
+                return "join the voice channel of the bot to vote"
+                    .to_string()
+                    .pipe(Err);
+            }
+
+            let listeners = VoiceStates::from_registry()
+                .send(GetListeners {
+                    guild: guild.0,
+                    channel,
+                })
+                .await
+                .expect("failed sending");
+            let required = required_votes(listeners, percent);
+
+            // kept with the track, not to carry votes over to the next one
+            let votes = {
+                let mut typemap = handles[0].typemap().write().await;
+                let mut votes = typemap.remove::<SkipVotes>().unwrap_or_default();
+                votes.insert(user);
+                let count = votes.len();
+                typemap.insert::<SkipVotes>(votes);
+                count
+            };
+
+            if votes < required {
+                return format!("voted to skip ({}/{})", votes, required).pipe(Ok);
+            }
+        }
+
+        let guard = call.lock().await;
+        let queue = guard.queue();
+
+        // the queue may have changed while checking
+        match queue.current() {
+            Some(h) if h.uuid() == handles[0].uuid() => (),
+            _ => return "the current track has changed".to_string().pipe(Err),
+        }
+
+        queue.modify_queue(|deq| deq.drain(1..count.min(deq.len())).for_each(drop));
+        queue.skip().map_err(|e| e.to_string())?;
+
+        match count {
+            1 => "skipped".to_string(),
+            n => format!("skipped {} tracks", n),
+        }
+        .pipe(Ok)
+    }
 }
 impl Handler<ControlAction> for Connector {
     type Result = ();
@@ -288,19 +821,76 @@ impl Handler<ControlAction> for Connector {
     ) -> Self::Result {
         let songbird = self.songbird.clone();
         let default_volumes = self.default_volumes.clone();
+        let history = self.history.clone();
 
         async move {
             use ControlActionKind::*;
             let result = match kind {
-                Enqueue { url } => Self::enqueue(songbird, default_volumes, guild, url).await,
+                Enqueue { source, user } =>
+                    Self::enqueue(
+                        songbird,
+                        default_volumes,
+                        history,
+                        guild,
+                        source,
+                        user,
+                        from.channel(),
+                    )
+                    .await,
+                Previous { user } =>
+                    Self::previous(
+                        songbird,
+                        default_volumes,
+                        history,
+                        guild,
+                        user,
+                        from.channel(),
+                    )
+                    .await,
+                Replay { index, user } =>
+                    Self::replay(
+                        songbird,
+                        default_volumes,
+                        history,
+                        guild,
+                        HistoryTarget::Index(index),
+                        user,
+                        from.channel(),
+                    )
+                    .await,
+                RequeueHistory { range, user } =>
+                    Self::replay(
+                        songbird,
+                        default_volumes,
+                        history,
+                        guild,
+                        HistoryTarget::Range(range),
+                        user,
+                        from.channel(),
+                    )
+                    .await,
+                EnqueuePlaylist { url, items, user } =>
+                    Self::enqueue_playlist(
+                        songbird,
+                        default_volumes,
+                        history,
+                        guild,
+                        url,
+                        items,
+                        user,
+                        from,
+                    )
+                    .await,
                 Pause => Self::pause(songbird, guild).await,
                 Resume => Self::resume(songbird, guild).await,
-                Loop => Self::r#loop(songbird, guild).await,
+                Loop { target, count } =>
+                    Self::r#loop(songbird, history, guild, target, count).await,
                 Shuffle => Self::shuffle(songbird, guild).await,
                 Volume {
                     percent,
                     current_only,
                 } => Self::volume(songbird, default_volumes, guild, percent, current_only).await,
+                Seek { time } => Self::seek(songbird, guild, time).await,
             };
 
             match result {
@@ -316,35 +906,319 @@ impl Connector {
     async fn enqueue(
         songbird: Arc<Songbird>,
         default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
         guild: impl Into<GuildId>,
-        url: String,
+        source: Source,
+        user: u64,
+        channel: u64,
     ) -> StringResult {
         let guild = guild.into();
 
-        Self::_enqueue(songbird, default_volumes, guild, url).await
+        Self::_enqueue(
+            songbird,
+            default_volumes,
+            history,
+            guild,
+            source,
+            user,
+            channel,
+        )
+        .await
     }
 
     async fn _enqueue(
         songbird: Arc<Songbird>,
         default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
         guild: GuildId,
-        url: String,
+        source: Source,
+        user: u64,
+        channel: u64,
     ) -> StringResult {
-        let call = Self::try_get_call(&songbird, guild)?;
+        let call = Self::join_if_needed(&songbird, &default_volumes, guild, user).await?;
+        let default_volume = *default_volumes.get(&guild.0).expect("must get value");
+
+        let (track, _) =
+            Self::create_track(history, guild, source, user, Some(channel), default_volume).await?;
+
+        call.lock().await.enqueue(track);
+
+        "enqueued".to_string().pipe(Ok)
+    }
+
+    async fn previous(
+        songbird: Arc<Songbird>,
+        default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: impl Into<GuildId>,
+        user: u64,
+        channel: u64,
+    ) -> StringResult {
+        let guild = guild.into();
+
+        Self::_previous(songbird, default_volumes, history, guild, user, channel).await
+    }
+
+    /// plays the last finished track before the current one, which resumes
+    /// after it
+    async fn _previous(
+        songbird: Arc<Songbird>,
+        default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: GuildId,
+        user: u64,
+        channel: u64,
+    ) -> StringResult {
+        let TrackInfo { data, .. } = history
+            .get(&guild.0)
+            .and_then(|h| h.last().cloned())
+            .ok_or_else(|| "no history".to_string())?;
+
+        let call = Self::join_if_needed(&songbird, &default_volumes, guild, user).await?;
+        let default_volume = *default_volumes.get(&guild.0).expect("must get value");
+
+        let title = data.title.unwrap_or_else(|| data.source.to_string());
+        let (track, handle) = Self::create_track(
+            history,
+            guild,
+            data.source,
+            user,
+            Some(channel),
+            default_volume,
+        )
+        .await?;
+
+        let mut guard = call.lock().await;
+        guard.enqueue(track);
+
+        let queue = guard.queue();
+        if let Some(current) = queue.current() {
+            if current.uuid() != handle.uuid() {
+                current.pause().map_err(|e| e.to_string())?;
+            }
+        }
+        queue.modify_queue(|deq| {
+            if let Some(i) = deq.iter().position(|q| q.uuid() == handle.uuid()) {
+                let queued = deq.remove(i).expect("must get value");
+                deq.push_front(queued);
+            }
+        });
+        handle.play().map_err(|e| e.to_string())?;
+
+        format!("playing previous: {}", title).pipe(Ok)
+    }
+
+    async fn replay(
+        songbird: Arc<Songbird>,
+        default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: impl Into<GuildId>,
+        target: HistoryTarget,
+        user: u64,
+        channel: u64,
+    ) -> StringResult {
+        let guild = guild.into();
+
+        Self::_replay(
+            songbird,
+            default_volumes,
+            history,
+            guild,
+            target,
+            user,
+            channel,
+        )
+        .await
+    }
+
+    /// enqueues tracks in the history (newest first, as `GetHistoryStatus`)
+    /// in the order they were played
+    async fn _replay(
+        songbird: Arc<Songbird>,
+        default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: GuildId,
+        target: HistoryTarget,
+        user: u64,
+        channel: u64,
+    ) -> StringResult {
+        let sources: Vec<Source> = {
+            let infos = history
+                .get(&guild.0)
+                .ok_or_else(|| "no history".to_string())?;
+
+            pick_history(&infos, target)?
+                .into_iter()
+                .map(|i| i.data.source.clone())
+                .collect()
+        };
+
+        let call = Self::join_if_needed(&songbird, &default_volumes, guild, user).await?;
         let default_volume = *default_volumes.get(&guild.0).expect("must get value");
 
-        let source = Restartable::ytdl(url, true)
+        let total = sources.len();
+        let mut added = 0;
+        for source in sources {
+            match Self::create_track(
+                history.clone(),
+                guild,
+                source,
+                user,
+                Some(channel),
+                default_volume,
+            )
+            .await
+            {
+                Ok((track, _)) => {
+                    call.lock().await.enqueue(track);
+                    added += 1;
+                },
+                Err(e) => tracing::warn!("failed creating track from history: {}", e),
+            }
+        }
+
+        match (total, added) {
+            (1, 1) => "enqueued".to_string().pipe(Ok),
+            (1, _) => "failed enqueueing".to_string().pipe(Err),
+            _ => format!("enqueued {} of {} tracks", added, total).pipe(Ok),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn enqueue_playlist(
+        songbird: Arc<Songbird>,
+        default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: impl Into<GuildId>,
+        url: String,
+        items: Option<(Bound<usize>, Bound<usize>)>,
+        user: u64,
+        from: MessageRef,
+    ) -> StringResult {
+        let guild = guild.into();
+
+        Self::_enqueue_playlist(
+            songbird,
+            default_volumes,
+            history,
+            guild,
+            url,
+            items,
+            user,
+            from,
+        )
+        .await
+    }
+
+    /// enqueues entries one by one, so that the first one can start playing
+    /// before resolving the rest
+    #[allow(clippy::too_many_arguments)]
+    async fn _enqueue_playlist(
+        songbird: Arc<Songbird>,
+        default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: GuildId,
+        url: String,
+        items: Option<(Bound<usize>, Bound<usize>)>,
+        user: u64,
+        from: MessageRef,
+    ) -> StringResult {
+        Self::join_if_needed(&songbird, &default_volumes, guild, user).await?;
+
+        let entries = source::expand_playlist(&url).await?;
+        let limit = config().limits.playlist;
+        let (selected, truncated) = source::select_items(entries, items, limit);
+
+        if selected.is_empty() {
+            return "no entries in the playlist (or range)"
+                .to_string()
+                .pipe(Err);
+        }
+
+        let total = selected.len();
+        match truncated {
+            true => reply(format!("enqueueing {} entries (limited)", total), from),
+            false => reply(format!("enqueueing {} entries", total), from),
+        }
+
+        let mut added = 0;
+        for url in selected {
+            // re-get every time, to stop when leaved while enqueueing
+            let call = Self::try_get_call(&songbird, guild)?;
+            let default_volume = match default_volumes.get(&guild.0) {
+                Some(v) => *v,
+                None => break,
+            };
+
+            let source = Source::Youtube { url };
+            let channel = Some(from.channel());
+            match Self::create_track(
+                history.clone(),
+                guild,
+                source,
+                user,
+                channel,
+                default_volume,
+            )
             .await
-            .map_err(|e| e.to_string())?
-            .into();
-        let (track, handle) = create_player(source);
+            {
+                Ok((track, _)) => {
+                    call.lock().await.enqueue(track);
+                    added += 1;
+                },
+                Err(e) => tracing::warn!("failed creating track from playlist: {}", e),
+            }
+        }
+
+        format!("enqueued {} of {} entries", added, total).pipe(Ok)
+    }
+
+    async fn create_track(
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: GuildId,
+        source: Source,
+        user: u64,
+        channel: Option<u64>,
+        volume: f32,
+    ) -> Result<(Track, TrackHandle), String> {
+        let input = source.input(user, guild.0).await?;
+
+        Self::track_from_input(history, guild, input, source, user, channel, volume).await
+    }
+
+    /// same as `create_track`, with `input` already created from `source`
+    async fn track_from_input(
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: GuildId,
+        input: Input,
+        source: Source,
+        user: u64,
+        channel: Option<u64>,
+        volume: f32,
+    ) -> Result<(Track, TrackHandle), String> {
+        let (track, handle) = create_player(input);
+        handle.set_volume(volume).map_err(|e| e.to_string())?;
+
+        let data = TrackData::new(source, user, channel, handle.metadata());
+        handle.typemap().write().await.insert::<TrackData>(data);
+
+        let recorder = HistoryRecorder {
+            history,
+            guild: guild.0,
+        };
         handle
-            .set_volume(default_volume)
+            .add_event(Event::Track(TrackEvent::End), recorder)
             .map_err(|e| e.to_string())?;
 
-        call.lock().await.enqueue(track);
+        let notifier = TrackNotifier { guild: guild.0 };
+        handle
+            .add_event(Event::Delayed(START_DELAY), notifier.clone())
+            .map_err(|e| e.to_string())?;
+        handle
+            .add_event(Event::Track(TrackEvent::End), notifier)
+            .map_err(|e| e.to_string())?;
 
-        "enqueued".to_string().pipe(Ok)
+        (track, handle).pipe(Ok)
     }
 
     async fn pause(songbird: Arc<Songbird>, guild: impl Into<GuildId>) -> StringResult {
@@ -381,31 +1255,105 @@ impl Connector {
         "resumed".to_string().pipe(Ok)
     }
 
-    async fn r#loop(songbird: Arc<Songbird>, guild: impl Into<GuildId>) -> StringResult {
+    async fn r#loop(
+        songbird: Arc<Songbird>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: impl Into<GuildId>,
+        target: LoopTarget,
+        count: LoopCount,
+    ) -> StringResult {
         let guild = guild.into();
 
-        Self::_loop(songbird, guild).await
+        Self::_loop(songbird, history, guild, target, count).await
     }
 
-    async fn _loop(songbird: Arc<Songbird>, guild: GuildId) -> StringResult {
+    async fn _loop(
+        songbird: Arc<Songbird>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        guild: GuildId,
+        target: LoopTarget,
+        count: LoopCount,
+    ) -> StringResult {
         let call = Self::try_get_call(&songbird, guild)?;
-        let guard = call.lock().await;
+        let handles = call.lock().await.queue().current_queue();
 
-        let handle = Self::try_get_handle(&guard)?;
-        let state = handle.get_info().await.map_err(|e| e.to_string())?.loops;
+        let range = match target {
+            LoopTarget::Index(index) => {
+                let handle = match handles.get(index) {
+                    Some(h) => h,
+                    None => return "out of bounds".to_string().pipe(Err),
+                };
 
-        use LoopState::*;
-        match state {
-            Finite(0) => handle
-                .enable_loop()
-                .map_err(|e| e.to_string())
-                .map(|()| "setted loop".to_string()),
+                use LoopCount::*;
+                let result = match count {
+                    Off => handle.disable_loop(),
+                    Finite(n) => handle.loop_for(n),
+                    Infinite => handle.enable_loop(),
+                };
+
+                return match count {
+                    Off => result.map(|()| "unsetted loop".to_string()),
+                    _ => result.map(|()| "setted loop".to_string()),
+                }
+                .map_err(|e| e.to_string());
+            },
+            LoopTarget::Range(range) => range,
+            LoopTarget::Queue => (Bound::Unbounded, Bound::Unbounded),
+        };
+
+        let end = match range.1 {
+            Bound::Unbounded => handles.len(),
+            Bound::Included(e) => e + 1,
+            Bound::Excluded(e) => e,
+        };
+        if end > handles.len() {
+            return "out of bounds".to_string().pipe(Err);
+        }
 
-            Finite(_) | Infinite => handle
-                .disable_loop()
-                .map_err(|e| e.to_string())
-                .map(|()| "unsetted loop".to_string()),
+        let targets = &handles[(range.0, Bound::Excluded(end))];
+        if targets.is_empty() {
+            return "no tracks to loop".to_string().pipe(Err);
         }
+
+        let block = NEXT_BLOCK.fetch_add(1, Ordering::Relaxed);
+        let mark = match count {
+            LoopCount::Off => None,
+            LoopCount::Finite(n) => Some(BlockLoop {
+                block,
+                remaining: Some(n),
+            }),
+            LoopCount::Infinite => Some(BlockLoop {
+                block,
+                remaining: None,
+            }),
+        };
+
+        for handle in targets {
+            let attached = {
+                let mut typemap = handle.typemap().write().await;
+                let attached = typemap.contains_key::<BlockLoop>();
+                typemap.insert::<BlockLoop>(mark);
+                attached
+            };
+
+            // the key exists if requeuer is already attached
+            if !attached && mark.is_some() {
+                let requeuer = LoopRequeuer {
+                    songbird: songbird.clone(),
+                    history: history.clone(),
+                    guild,
+                };
+                handle
+                    .add_event(Event::Track(TrackEvent::End), requeuer)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        match mark {
+            Some(_) => format!("setted loop of {} tracks", targets.len()),
+            None => format!("unsetted loop of {} tracks", targets.len()),
+        }
+        .pipe(Ok)
     }
 
     async fn shuffle(songbird: Arc<Songbird>, guild: impl Into<GuildId>) -> StringResult {
@@ -478,6 +1426,39 @@ impl Connector {
         .to_string()
         .pipe(Ok)
     }
+
+    async fn seek(
+        songbird: Arc<Songbird>,
+        guild: impl Into<GuildId>,
+        time: TimeSpec,
+    ) -> StringResult {
+        let guild = guild.into();
+
+        Self::_seek(songbird, guild, time).await
+    }
+
+    async fn _seek(songbird: Arc<Songbird>, guild: GuildId, time: TimeSpec) -> StringResult {
+        let call = Self::try_get_call(&songbird, guild)?;
+        let guard = call.lock().await;
+
+        let handle = Self::try_get_handle(&guard)?;
+        if !handle.is_seekable() {
+            return "current track is not seekable".to_string().pipe(Err);
+        }
+
+        let position = handle.get_info().await.map_err(|e| e.to_string())?.position;
+        let target = time.apply(position);
+
+        if let Some(duration) = handle.metadata().duration {
+            if target > duration {
+                return "out of bounds".to_string().pipe(Err);
+            }
+        }
+
+        handle.seek_time(target).map_err(|e| e.to_string())?;
+
+        format!("seeked to {}s", target.as_secs_f32()).pipe(Ok)
+    }
 }
 impl Handler<GetCurrentStatus> for Connector {
     type Result = ResponseFuture<Result<CurrentStatus, String>>;
@@ -495,7 +1476,7 @@ impl Handler<GetCurrentStatus> for Connector {
                 let guard = call.lock().await;
 
                 let handle = Self::try_get_handle(&guard)?;
-                handle.get_info().await.map_err(|e| e.to_string())?.into()
+                TrackStatus::of(&handle).await?
             };
 
             result.map(|current_track| CurrentStatus { current_track })
@@ -503,125 +1484,397 @@ impl Handler<GetCurrentStatus> for Connector {
         .pipe(Box::pin)
     }
 }
-impl Handler<GetQueueStatus> for Connector {
-    type Result = ResponseFuture<Result<QueueStatus, String>>;
+impl Handler<GetQueueTitles> for Connector {
+    type Result = ResponseFuture<Result<Vec<String>, String>>;
 
     fn handle(
         &mut self,
-        GetQueueStatus { guild, page }: GetQueueStatus,
+        GetQueueTitles { guild }: GetQueueTitles,
         _: &mut Self::Context,
     ) -> Self::Result {
         let songbird = self.songbird.clone();
 
         async move {
-            if page == 0 {
-                return "cannot specify page under 1".to_string().pipe(Err);
+            let call = Self::try_get_call(&songbird, guild.into())?;
+            let handles = call.lock().await.queue().current_queue();
+
+            let mut titles = vec![];
+            for handle in handles {
+                let title = match handle.typemap().read().await.get::<TrackData>() {
+                    Some(TrackData {
+                        title: Some(title), ..
+                    }) => title.clone(),
+                    Some(TrackData { source, .. }) => source.to_string(),
+                    None => "unknown".to_string(),
+                };
+                titles.push(title);
             }
 
-            let result: Result<_, String> = try {
-                let call = Self::try_get_call(&songbird, guild.into())?;
-                let guard = call.lock().await;
+            Ok(titles)
+        }
+        .pipe(Box::pin)
+    }
+}
+impl Handler<GetQueueStatus> for Connector {
+    type Result = ResponseFuture<Result<QueueStatus, String>>;
 
-                let mut queue = guard
-                    .queue()
-                    .current_queue()
-                    .into_iter()
-                    .enumerate()
-                    .collect::<Vec<_>>();
+    fn handle(
+        &mut self,
+        GetQueueStatus { guild, page }: GetQueueStatus,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let songbird = self.songbird.clone();
 
-                const ITEMS: usize = 10;
-                let start = ITEMS * (page - 1);
-                let mut end = ITEMS * page;
-                if start > queue.len() {
-                    return "out of bounds".to_string().pipe(Err);
-                }
-                if end > queue.len() {
-                    end = queue.len();
-                }
-                let paging = start..end;
-
-                let mut ok_vec = vec![];
-                let mut err_vec = vec![];
-                for (i, h) in queue.drain(paging) {
-                    match h.get_info().await {
-                        Ok(ts) => ok_vec.push((i, ts.into())),
-                        Err(e) => err_vec.push(e.to_string()),
-                    }
-                }
-                (ok_vec, err_vec)
-            };
+        async move {
+            let page_size = Self::settings(guild).await.page_size();
+            let call = Self::try_get_call(&songbird, guild.into())?;
+            let queue = call.lock().await.queue().current_queue();
+
+            let (pages, range) = paginate(queue.len(), page, page_size)?;
+
+            let mut remaining = Duration::ZERO;
+            let mut unknown = 0;
+            for (i, handle) in queue.iter().enumerate() {
+                let duration = match handle.metadata().duration {
+                    Some(d) => d,
+                    None => {
+                        unknown += 1;
+                        continue;
+                    },
+                };
+                let position = match i {
+                    0 => handle
+                        .get_info()
+                        .await
+                        .map_or(Duration::ZERO, |s| s.position),
+                    _ => Duration::ZERO,
+                };
 
-            let (oks, errs) = match result {
-                Ok(o) => o,
-                Err(e) => return Err(e),
-            };
+                remaining += duration.saturating_sub(position);
+            }
 
-            if errs.is_empty() {
-                let mut buf = String::new();
-                errs.into_iter()
-                    .enumerate()
-                    .for_each(|(i, e)| write!(buf, "{}: {}", i, e).unwrap());
+            let mut tracks = vec![];
+            let mut errs = vec![];
+            for i in range {
+                match TrackStatus::of(&queue[i]).await {
+                    Ok(ts) => tracks.push((i, ts)),
+                    Err(e) => errs.push(format!("{}: {}", i, e)),
+                }
+            }
 
-                return Err(buf);
+            if !errs.is_empty() {
+                return errs.join("\n").pipe(Err);
             }
 
-            QueueStatus { tracks: oks }.pipe(Ok)
+            QueueStatus {
+                tracks,
+                page,
+                pages,
+                count: queue.len(),
+                remaining,
+                unknown,
+            }
+            .pipe(Ok)
         }
         .pipe(Box::pin)
     }
 }
 impl Handler<GetHistoryStatus> for Connector {
-    type Result = Result<HistoryStatus, String>;
+    type Result = ResponseFuture<Result<HistoryStatus, String>>;
 
     fn handle(
         &mut self,
         GetHistoryStatus { guild, page }: GetHistoryStatus,
         _: &mut Self::Context,
     ) -> Self::Result {
-        self.history
-            .get(&guild)
-            .map(|v| {
-                const ITEMS: usize = 10;
-                let start = ITEMS * (page - 1);
-                let mut end = ITEMS * page;
-                if start > v.len() {
-                    return "out of bounds".to_string().pipe(Err);
-                }
-                if end > v.len() {
-                    end = v.len();
+        let history = self.history.clone();
+
+        async move {
+            let page_size = Self::settings(guild).await.page_size();
+            let history = history
+                .get(&guild)
+                .ok_or_else(|| "no history".to_string())?;
+
+            let (pages, range) = paginate(history.len(), page, page_size)?;
+
+            // newest first
+            HistoryStatus {
+                history: newest_first(&history)
+                    .skip(range.start)
+                    .take(range.len())
+                    .map(|(i, t)| (i, t.clone()))
+                    .collect(),
+                page,
+                pages,
+                count: history.len(),
+            }
+            .pipe(Ok)
+        }
+        .pipe(Box::pin)
+    }
+}
+impl Handler<GetCallsStatus> for Connector {
+    type Result = ResponseFuture<CallsStatus>;
+
+    fn handle(&mut self, GetCallsStatus: GetCallsStatus, _: &mut Self::Context) -> Self::Result {
+        let songbird = self.songbird.clone();
+        let guilds = self
+            .default_volumes
+            .iter()
+            .map(|r| *r.key())
+            .collect::<Vec<_>>();
+
+        async move {
+            let mut calls = vec![];
+            for guild in guilds {
+                let channel = match songbird.get(guild) {
+                    Some(call) => call.lock().await.current_channel().map(|c| c.0),
+                    None => None,
+                };
+
+                calls.push((guild, channel));
+            }
+
+            CallsStatus { calls }
+        }
+        .pipe(Box::pin)
+    }
+}
+impl Handler<Restore> for Connector {
+    type Result = ();
+
+    fn handle(&mut self, Restore: Restore, ctx: &mut Self::Context) -> Self::Result {
+        let songbird = self.songbird.clone();
+        let default_volumes = self.default_volumes.clone();
+        let history = self.history.clone();
+
+        let snapshots = match self.storage.load() {
+            Ok(o) => o,
+            Err(e) => {
+                tracing::error!("failed loading snapshots: {}", e);
+                return;
+            },
+        };
+
+        async move {
+            for snapshot in snapshots {
+                let guild = snapshot.guild;
+                let result = Self::restore(
+                    songbird.clone(),
+                    default_volumes.clone(),
+                    history.clone(),
+                    snapshot,
+                )
+                .await;
+
+                match result {
+                    Ok((added, total)) => {
+                        tracing::info!("restored {} with {} of {} tracks", guild, added, total)
+                    },
+                    Err(e) => tracing::warn!("failed restoring {}: {}", guild, e),
                 }
-                let paging = start..end;
-
-                HistoryStatus {
-                    history: v
-                        .iter()
-                        .collect::<Vec<_>>()
-                        .drain(paging)
-                        .cloned()
-                        .enumerate()
-                        .collect(),
+            }
+        }
+        .into_actor(self)
+        .spawn(ctx);
+    }
+}
+impl Connector {
+    async fn restore(
+        songbird: Arc<Songbird>,
+        default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+        GuildSnapshot {
+            guild,
+            channel,
+            default_volume,
+            queue,
+            history: past,
+        }: GuildSnapshot,
+    ) -> Result<(usize, usize), String> {
+        history.insert(guild, past);
+
+        let channel = match channel {
+            Some(c) => c,
+            None => return Ok((0, 0)),
+        };
+
+        Self::_join(
+            songbird.clone(),
+            default_volumes.clone(),
+            guild.into(),
+            channel.into(),
+        )
+        .await?;
+        default_volumes.insert(guild, default_volume);
+
+        let call = Self::try_get_call(&songbird, guild.into())?;
+        let total = queue.len();
+        let mut added = 0;
+        for QueuedTrack { data, position } in queue {
+            let (track, handle) = match Self::create_track(
+                history.clone(),
+                guild.into(),
+                data.source,
+                data.user,
+                data.channel,
+                default_volume,
+            )
+            .await
+            {
+                Ok(o) => o,
+                Err(e) => {
+                    tracing::warn!("failed restoring track of {}: {}", guild, e);
+                    continue;
+                },
+            };
+
+            call.lock().await.enqueue(track);
+            added += 1;
+
+            if !position.is_zero() {
+                if let Err(e) = handle.seek_time(position) {
+                    tracing::warn!("failed seeking restored track of {}: {}", guild, e);
                 }
-                .pipe(Ok)
-            })
-            .ok_or_else(|| "no history".to_string())
-            .flatten()
+            }
+        }
+
+        Ok((added, total))
+    }
+
+    async fn save_snapshots(
+        songbird: Arc<Songbird>,
+        storage: Arc<dyn Storage>,
+        default_volumes: Arc<DashMap<u64, f32>>,
+        history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+    ) {
+        let guilds = default_volumes
+            .iter()
+            .map(|r| (*r.key(), *r.value()))
+            .collect::<Vec<_>>();
+
+        for (guild, default_volume) in guilds {
+            // removed without leaving (e.g. kicked), not to rejoin on restoring
+            let call = match songbird.get(guild) {
+                Some(c) => c,
+                None => {
+                    if let Err(e) = storage.remove(guild) {
+                        tracing::warn!("failed removing snapshot of {}: {}", guild, e);
+                    }
+                    continue;
+                },
+            };
+
+            let (channel, handles) = {
+                let guard = call.lock().await;
+                (
+                    guard.current_channel().map(|c| c.0),
+                    guard.queue().current_queue(),
+                )
+            };
+
+            let mut queue = vec![];
+            for handle in handles {
+                let data = match handle.typemap().read().await.get::<TrackData>().cloned() {
+                    Some(d) => d,
+                    None => continue,
+                };
+                let position = handle
+                    .get_info()
+                    .await
+                    .map(|s| s.position)
+                    .unwrap_or_default();
+
+                queue.push(QueuedTrack { data, position });
+            }
+
+            let snapshot = GuildSnapshot {
+                guild,
+                channel,
+                default_volume,
+                queue,
+                history: history.get(&guild).map(|v| v.clone()).unwrap_or_default(),
+            };
+
+            if let Err(e) = storage.save(&snapshot) {
+                tracing::warn!("failed saving snapshot of {}: {}", guild, e);
+            }
+        }
+    }
+}
+impl Handler<Disconnect> for Connector {
+    type Result = ResponseFuture<StringResult>;
+
+    fn handle(&mut self, Disconnect { guild }: Disconnect, _: &mut Self::Context) -> Self::Result {
+        let songbird = self.songbird.clone();
+        let storage = self.storage.clone();
+        let default_volumes = self.default_volumes.clone();
+
+        Self::leave(songbird, storage, default_volumes, guild).pipe(Box::pin)
     }
 }
 impl Supervised for Connector {}
 impl ArbiterService for Connector {}
 
+pub struct Restore;
+impl Message for Restore {
+    type Result = ();
+}
+
+/// leaves without replying, e.g. by policies
+pub struct Disconnect {
+    pub guild: u64,
+}
+impl Message for Disconnect {
+    type Result = Result<String, String>;
+}
+
 pub struct CallAction {
     pub kind: CallActionKind,
     pub from: MessageRef,
     pub guild: u64,
 }
 pub enum CallActionKind {
-    Join { channel: u64 },
+    Join {
+        channel: u64,
+    },
     Leave,
-    Slide { from: usize, to: usize },
-    Drop { kind: DropKind },
-    Fix,
+    Slide {
+        from: usize,
+        to: usize,
+    },
+    Drop {
+        kind: DropKind,
+        /// only drops tracks enqueued by the user if specified
+        requester: Option<u64>,
+    },
+    /// rejoins the channel of `user` if disconnected and no channel is saved
+    Fix {
+        user: u64,
+    },
     Stop,
+    /// skips `count` tracks from the current one, or votes to skip it
+    Skip {
+        count: usize,
+        user: u64,
+        access: Access,
+    },
+}
+pub enum LoopTarget {
+    Index(usize),
+    /// loops tracks in the range as a block
+    Range((Bound<usize>, Bound<usize>)),
+    /// loops all tracks in the queue as a block
+    Queue,
+}
+pub enum HistoryTarget {
+    Index(usize),
+    Range((Bound<usize>, Bound<usize>)),
+}
+pub enum LoopCount {
+    Off,
+    Finite(usize),
+    Infinite,
 }
 pub enum DropKind {
     Index(usize),
@@ -637,12 +1890,43 @@ pub struct ControlAction {
     pub guild: u64,
 }
 pub enum ControlActionKind {
-    Enqueue { url: String },
+    Enqueue {
+        source: Source,
+        user: u64,
+    },
+    EnqueuePlaylist {
+        url: String,
+        items: Option<(Bound<usize>, Bound<usize>)>,
+        user: u64,
+    },
+    /// plays the last finished track, then resumes the current one
+    Previous {
+        user: u64,
+    },
+    /// enqueues the track at the index of the history (0 is newest)
+    Replay {
+        index: usize,
+        user: u64,
+    },
+    /// enqueues tracks in the range of the history, oldest first
+    RequeueHistory {
+        range: (Bound<usize>, Bound<usize>),
+        user: u64,
+    },
     Pause,
     Resume,
-    Loop, // FIXME: uncomplete
+    Loop {
+        target: LoopTarget,
+        count: LoopCount,
+    },
     Shuffle,
-    Volume { percent: f32, current_only: bool },
+    Volume {
+        percent: f32,
+        current_only: bool,
+    },
+    Seek {
+        time: TimeSpec,
+    },
 }
 impl Message for ControlAction {
     type Result = ();
@@ -664,27 +1948,69 @@ pub struct GetQueueStatus {
 }
 pub struct QueueStatus {
     pub tracks: Vec<(usize, TrackStatus)>,
+    pub page: usize,
+    pub pages: usize,
+    pub count: usize,
+    /// of tracks with known durations
+    pub remaining: Duration,
+    /// number of tracks with unknown durations
+    pub unknown: usize,
 }
 impl Message for GetQueueStatus {
     type Result = Result<QueueStatus, String>;
 }
+/// titles of tracks in the queue, for completion
+pub struct GetQueueTitles {
+    pub guild: u64,
+}
+impl Message for GetQueueTitles {
+    type Result = Result<Vec<String>, String>;
+}
 pub struct GetHistoryStatus {
     pub guild: u64,
     pub page: usize,
 }
 pub struct HistoryStatus {
     pub history: Vec<(usize, TrackInfo)>,
+    pub page: usize,
+    pub pages: usize,
+    pub count: usize,
 }
 impl Message for GetHistoryStatus {
     type Result = Result<HistoryStatus, String>;
 }
 
+pub struct GetCallsStatus;
+pub struct CallsStatus {
+    pub calls: Vec<(u64, Option<u64>)>,
+}
+impl Message for GetCallsStatus {
+    type Result = CallsStatus;
+}
+
 pub struct TrackStatus {
+    pub data: Option<TrackData>,
     pub mode: TrackMode,
     pub volume: f32,
     pub position: Duration,
     pub total: Duration,
     pub loops: TrackLoop,
+    pub block_loop: Option<BlockLoop>,
+}
+impl TrackStatus {
+    async fn of(handle: &TrackHandle) -> Result<Self, String> {
+        let state = handle.get_info().await.map_err(|e| e.to_string())?;
+        let typemap = handle.typemap().read().await;
+        let data = typemap.get::<TrackData>().cloned();
+        let block_loop = typemap.get::<BlockLoop>().copied().flatten();
+
+        TrackStatus {
+            data,
+            block_loop,
+            ..state.into()
+        }
+        .pipe(Ok)
+    }
 }
 pub enum TrackMode {
     Play,
@@ -714,7 +2040,8 @@ impl Display for TrackLoop {
         use TrackLoop::*;
         let s = match self {
             Infinite => "Infinite".to_string(),
-            Finite(t) => format!("Finite - until {} times", t),
+            Finite(0) => "Off".to_string(),
+            Finite(t) => format!("Finite - {} more times", t),
         };
 
         write!(f, "{}", s)
@@ -731,11 +2058,13 @@ impl From<TrackState> for TrackStatus {
         }: TrackState,
     ) -> Self {
         TrackStatus {
+            data: None,
             mode: playing.into(),
             volume,
             position,
             total: play_time,
             loops: loops.into(),
+            block_loop: None,
         }
     }
 }
@@ -760,7 +2089,337 @@ impl From<LoopState> for TrackLoop {
         }
     }
 }
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrackData {
+    pub source: Source,
+    pub user: u64,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub duration: Option<Duration>,
+    pub thumbnail: Option<String>,
+    pub source_url: Option<String>,
+    /// where enqueued, to announce
+    #[serde(default)]
+    pub channel: Option<u64>,
+}
+impl TrackData {
+    fn new(source: Source, user: u64, channel: Option<u64>, metadata: &Metadata) -> Self {
+        let Metadata {
+            title,
+            artist,
+            channel: uploader,
+            duration,
+            thumbnail,
+            source_url,
+            ..
+        } = metadata.clone();
+
+        Self {
+            source,
+            user,
+            title,
+            artist: artist.or(uploader),
+            duration,
+            thumbnail,
+            source_url,
+            channel,
+        }
+    }
+}
+impl TypeMapKey for TrackData {
+    type Value = TrackData;
+}
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TrackInfo {
-    pub url: String,
+    pub data: TrackData,
+    pub finished_at: SystemTime,
+    pub position: Duration,
+}
+
+/// users who voted to skip the track
+struct SkipVotes;
+impl TypeMapKey for SkipVotes {
+    type Value = HashSet<u64>;
+}
+
+/// marks a track as a member of looped block (`None` if unsetted)
+#[derive(Clone, Copy)]
+pub struct BlockLoop {
+    pub block: u64,
+    pub remaining: Option<usize>,
+}
+impl TypeMapKey for BlockLoop {
+    type Value = Option<BlockLoop>;
+}
+
+/// recreates the finished track and puts it after the rest of its block
+#[derive(Clone)]
+struct LoopRequeuer {
+    songbird: Arc<Songbird>,
+    history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+    guild: GuildId,
+}
+#[async_trait]
+impl EventHandler for LoopRequeuer {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let (state, handle) = match ctx {
+            EventContext::Track(&[(state, handle)]) => (state, handle),
+            _ => return None,
+        };
+
+        // stopped or skipped, not finished
+        if state.playing != PlayMode::End {
+            return None;
+        }
+
+        let (mark, data) = {
+            let typemap = handle.typemap().read().await;
+            (
+                (*typemap.get::<BlockLoop>()?)?,
+                typemap.get::<TrackData>()?.clone(),
+            )
+        };
+        let remaining = match mark.remaining {
+            Some(0) => return None,
+            Some(n) => Some(n - 1),
+            None => None,
+        };
+
+        let call = self.songbird.get(self.guild)?;
+
+        // the queue may advance while recreating, so positions are kept by uuids
+        let mut anchor = Anchor::Back;
+        let handles = call.lock().await.queue().current_queue();
+        for other in handles.iter().rev() {
+            if other.uuid() == handle.uuid() {
+                continue;
+            }
+
+            let block = other
+                .typemap()
+                .read()
+                .await
+                .get::<BlockLoop>()
+                .copied()
+                .flatten();
+            if block.map(|b| b.block) == Some(mark.block) {
+                anchor = Anchor::After(other.clone());
+                break;
+            }
+        }
+        if matches!(anchor, Anchor::Back) {
+            // no other members, so plays again before the track after it
+            // (after, if that has started already)
+            let next = match handles.iter().position(|h| h.uuid() == handle.uuid()) {
+                Some(i) => handles.get(i + 1),
+                None => handles.first(),
+            };
+            if let Some(next) = next {
+                anchor = Anchor::Before(next.clone());
+            }
+        }
+
+        let created: Result<_, String> = try {
+            let input = data
+                .source
+                .input_with(data.user, self.guild.0, handle.metadata())
+                .await?;
+            Connector::track_from_input(
+                self.history.clone(),
+                self.guild,
+                input,
+                data.source,
+                data.user,
+                data.channel,
+                state.volume,
+            )
+            .await?
+        };
+        let (track, requeued) = match created {
+            Ok(o) => o,
+            Err(e) => {
+                tracing::warn!("failed requeueing looped track: {}", e);
+                return None;
+            },
+        };
+
+        let mark = BlockLoop { remaining, ..mark };
+        requeued
+            .typemap()
+            .write()
+            .await
+            .insert::<BlockLoop>(Some(mark));
+        if let Err(e) = requeued.add_event(Event::Track(TrackEvent::End), self.clone()) {
+            tracing::warn!("failed requeueing looped track: {}", e);
+            return None;
+        }
+
+        let mut guard = call.lock().await;
+        guard.enqueue(track);
+        guard.queue().modify_queue(|deq| {
+            let requeued = deq.pop_back().expect("must have enqueued track");
+
+            let index = match anchor {
+                Anchor::After(a) => deq.iter().position(|q| q.uuid() == a.uuid()).map(|i| i + 1),
+                Anchor::Before(b) => deq.iter().position(|q| q.uuid() == b.uuid()),
+                Anchor::Back => None,
+            }
+            .unwrap_or(deq.len());
+
+            // the front is playing (or started by the queue on ending), so
+            // puts it after that if the queue has advanced already
+            let index = index.max(1).min(deq.len());
+            deq.insert(index, requeued);
+        });
+
+        None
+    }
+}
+
+/// where to put the requeued track
+enum Anchor {
+    After(TrackHandle),
+    Before(TrackHandle),
+    Back,
+}
+
+struct HistoryRecorder {
+    history: Arc<DashMap<u64, Vec<TrackInfo>>>,
+    guild: u64,
+}
+#[async_trait]
+impl EventHandler for HistoryRecorder {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let (state, handle) = match ctx {
+            EventContext::Track(&[(state, handle)]) => (state, handle),
+            _ => return None,
+        };
+
+        // stopped before playing (e.g. `stop` with queued tracks)
+        if state.play_time.is_zero() {
+            return None;
+        }
+
+        let data = handle.typemap().read().await.get::<TrackData>().cloned()?;
+
+        let mut history = self.history.entry(self.guild).or_default();
+        history.push(TrackInfo {
+            data,
+            finished_at: SystemTime::now(),
+            position: state.position,
+        });
+
+        let limit = config().limits.history;
+        if history.len() > limit {
+            let overflow = history.len() - limit;
+            history.drain(..overflow);
+        }
+
+        None
+    }
+}
+
+/// notifies `Announcer` of start and end of the track
+#[derive(Clone)]
+struct TrackNotifier {
+    guild: u64,
+}
+#[async_trait]
+impl EventHandler for TrackNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let (state, handle) = match ctx {
+            EventContext::Track(&[(state, handle)]) => (state, handle),
+            _ => return None,
+        };
+
+        let data = handle.typemap().read().await.get::<TrackData>().cloned()?;
+
+        let kind = match state.playing {
+            // ended far before its duration, e.g. failed fetching
+            PlayMode::End
+                if data
+                    .duration
+                    .is_some_and(|d| state.position + ERROR_MARGIN < d) =>
+                TrackNoticeKind::Failed,
+            PlayMode::End | PlayMode::Stop => TrackNoticeKind::Finished,
+            _ => TrackNoticeKind::Started,
+        };
+
+        Announcer::from_registry()
+            .try_send(TrackNotice {
+                guild: self.guild,
+                data,
+                kind,
+            })
+            .expect("failed sending");
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_by_access() {
+        use Access::*;
+
+        assert_eq!(skip_by(Privileged, false, Some(50), 3), Ok(SkipBy::Instant));
+        assert_eq!(skip_by(OwnTracks, true, Some(50), 3), Ok(SkipBy::Instant));
+        assert_eq!(skip_by(All, false, None, 3), Ok(SkipBy::Instant));
+        assert_eq!(skip_by(All, false, Some(50), 1), Ok(SkipBy::Vote(50)));
+        assert_eq!(skip_by(OwnTracks, false, Some(50), 1), Ok(SkipBy::Vote(50)));
+
+        assert!(skip_by(OwnTracks, false, None, 1).is_err());
+        assert!(skip_by(All, false, Some(50), 2).is_err());
+        assert!(skip_by(OwnTracks, false, Some(50), 2).is_err());
+    }
+
+    #[test]
+    fn required_votes_of_listeners() {
+        assert_eq!(required_votes(4, 50), 2);
+        assert_eq!(required_votes(5, 50), 3);
+        assert_eq!(required_votes(3, 100), 3);
+        assert_eq!(required_votes(1, 1), 1);
+        // at least one, even if no listeners or 0%
+        assert_eq!(required_votes(0, 50), 1);
+        assert_eq!(required_votes(10, 0), 1);
+    }
+
+    #[test]
+    fn pick_history_by_numbers() {
+        // oldest first, as stored
+        let history = ["a", "b", "c", "d"];
+        let numbers = newest_first(&history)
+            .map(|(i, t)| (*t, i))
+            .collect::<std::collections::HashMap<_, _>>();
+        assert_eq!(numbers["d"], 0);
+        assert_eq!(numbers["a"], 3);
+
+        let pick = |target| {
+            pick_history(&history, target).map(|v| v.into_iter().copied().collect::<Vec<_>>())
+        };
+        let range = |start, end| HistoryTarget::Range((start, end));
+
+        assert_eq!(pick(HistoryTarget::Index(0)), Ok(vec!["d"]));
+        assert_eq!(pick(HistoryTarget::Index(numbers["b"])), Ok(vec!["b"]));
+        assert!(pick(HistoryTarget::Index(4)).is_err());
+
+        // "1..=2" picks "c" and "b", enqueued in the played order
+        assert_eq!(
+            pick(range(Bound::Included(1), Bound::Included(2))),
+            Ok(vec!["b", "c"])
+        );
+        assert_eq!(
+            pick(range(Bound::Unbounded, Bound::Excluded(2))),
+            Ok(vec!["c", "d"])
+        );
+        assert_eq!(
+            pick(range(Bound::Included(2), Bound::Unbounded)),
+            Ok(vec!["a", "b"])
+        );
+        assert!(pick(range(Bound::Included(2), Bound::Excluded(2))).is_err());
+        assert!(pick(range(Bound::Included(3), Bound::Included(4))).is_err());
+    }
 }
@@ -1,23 +1,81 @@
+use alloc::sync::Arc;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
 use actix::prelude::{
     Actor, ArbiterService, Context, ContextFutureSpawner, Handler, Message, Supervised, WrapFuture,
 };
 use twilight_http::Client;
+use twilight_model::application::component::button::ButtonStyle;
+use twilight_model::application::component::{ActionRow, Button, Component};
+use twilight_model::channel::embed::Embed;
+use twilight_model::http::attachment::Attachment as HttpAttachment;
+use twilight_model::id::marker::{ApplicationMarker, ChannelMarker, MessageMarker};
+use twilight_model::id::Id;
+use twilight_util::builder::embed::{
+    EmbedBuilder, EmbedFieldBuilder, EmbedFooterBuilder, ImageSource,
+};
 
 use crate::command::CommandParser;
 use crate::util::{token, Pipe};
 
+/// tokens of interactions are valid for 15 minutes
+const INTERACTION_TTL: Duration = Duration::from_secs(15 * 60);
+/// characters per message
+const CONTENT_LIMIT: usize = 2000;
+/// messages per reply, or attached as a file
+const PARTS_LIMIT: usize = 3;
+
+/// where to reply
 #[derive(Debug, Clone, Copy)]
-pub struct MessageRef {
-    pub message: u64,
-    pub channel: u64,
+pub enum MessageRef {
+    Message {
+        message: u64,
+        channel: u64,
+    },
+    /// the token must be registered to `Responder` with `RegisterInteraction`
+    Interaction {
+        id: u64,
+        channel: u64,
+    },
+}
+impl MessageRef {
+    pub fn channel(&self) -> u64 {
+        match *self {
+            MessageRef::Message { channel, .. } => channel,
+            MessageRef::Interaction { channel, .. } => channel,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub filename: String,
+    pub url: String,
+    pub size: u64,
+    pub content_type: Option<String>,
+}
+
+impl From<twilight_model::channel::Attachment> for Attachment {
+    fn from(attachment: twilight_model::channel::Attachment) -> Self {
+        Self {
+            filename: attachment.filename,
+            url: attachment.url,
+            size: attachment.size,
+            content_type: attachment.content_type,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct RawCommand {
     pub content: String,
+    pub attachments: Vec<Attachment>,
     pub user: u64,
     pub from: MessageRef,
     pub guild: Option<u64>,
+    /// roles of the author in the guild, if known
+    pub roles: Option<Vec<u64>>,
 }
 impl Message for RawCommand {
     type Result = ();
@@ -35,18 +93,22 @@ impl Handler<GatewayMessage> for Gateway {
         &mut self,
         GatewayMessage {
             content,
+            attachments,
             from,
             user,
             guild,
+            roles,
         }: GatewayMessage,
         _: &mut Self::Context,
     ) -> Self::Result {
         CommandParser::from_registry()
             .try_send(RawCommand {
                 content,
+                attachments,
                 from,
                 user,
                 guild,
+                roles,
             })
             .expect("failed sending")
     }
@@ -56,9 +118,12 @@ impl ArbiterService for Gateway {}
 
 pub struct GatewayMessage {
     pub content: String,
+    pub attachments: Vec<Attachment>,
     pub user: u64,
     pub from: MessageRef,
     pub guild: Option<u64>,
+    /// roles of the author in the guild, if known
+    pub roles: Option<Vec<u64>>,
 }
 
 impl Message for GatewayMessage {
@@ -66,12 +131,14 @@ impl Message for GatewayMessage {
 }
 
 pub struct Responder {
-    client: Client,
+    client: Arc<Client>,
+    interactions: HashMap<u64, InteractionToken>,
 }
 impl Default for Responder {
     fn default() -> Self {
         Self {
-            client: Client::new(token()),
+            client: Client::new(token()).pipe(Arc::new),
+            interactions: HashMap::new(),
         }
     }
 }
@@ -81,30 +148,187 @@ impl Actor for Responder {
 impl Handler<Reply> for Responder {
     type Result = Result<(), core::num::TryFromIntError>;
 
+    fn handle(&mut self, Reply { payload, to }: Reply, ctx: &mut Self::Context) -> Self::Result {
+        let client = self.client.clone();
+
+        let target = match to {
+            MessageRef::Message { message, channel } => Target::Message {
+                channel: channel.try_into()?,
+                reply: Some(message.try_into()?),
+            },
+            MessageRef::Interaction { id, .. } => match self.take_interaction(id) {
+                Some(t) => t,
+                None => {
+                    tracing::error!("unknown or expired interaction: {}", id);
+                    return Ok(());
+                },
+            },
+        };
+
+        async move {
+            if let Err(e) = Self::deliver(client, target, payload).await {
+                tracing::error!("failed sending response: {}", e);
+            }
+        }
+        .into_actor(self)
+        .spawn(ctx);
+
+        Ok(())
+    }
+}
+impl Responder {
+    /// marks as responded, since replies are sent in order
+    fn take_interaction(&mut self, id: u64) -> Option<Target> {
+        let InteractionToken {
+            application,
+            token,
+            responded,
+            ..
+        } = self.interactions.get_mut(&id)?;
+
+        let target = Target::Interaction {
+            application: *application,
+            token: token.clone(),
+            responded: *responded,
+        };
+        *responded = true;
+
+        Some(target)
+    }
+
+    /// sends the embed, or the text split into parts if failed
+    async fn deliver(
+        client: Arc<Client>,
+        mut target: Target,
+        payload: Payload,
+    ) -> Result<(), String> {
+        if let Some(embed) = payload.embed() {
+            let part = Part {
+                embeds: vec![embed],
+                components: payload.components(),
+                ..Default::default()
+            };
+
+            match Self::send(&client, &mut target, part).await {
+                Ok(()) => return Ok(()),
+                Err(e) => tracing::warn!("failed sending embed, falling back to text: {}", e),
+            }
+        }
+
+        for part in text_parts(&payload.text()) {
+            Self::send(&client, &mut target, part).await?;
+        }
+
+        Ok(())
+    }
+
+    /// the first message replies (or replaces the deferred response), and the
+    /// rest are sent to the channel (or as followups)
+    async fn send(client: &Client, target: &mut Target, part: Part) -> Result<(), String> {
+        match target {
+            Target::Message { channel, reply } => {
+                let mut req = client.create_message(*channel);
+                if let Some(content) = &part.content {
+                    req = req.content(content).map_err(|e| e.to_string())?;
+                }
+                if !part.embeds.is_empty() {
+                    req = req.embeds(&part.embeds).map_err(|e| e.to_string())?;
+                }
+                if !part.attachments.is_empty() {
+                    req = req
+                        .attachments(&part.attachments)
+                        .map_err(|e| e.to_string())?;
+                }
+                if !part.components.is_empty() {
+                    req = req
+                        .components(&part.components)
+                        .map_err(|e| e.to_string())?;
+                }
+                if let Some(message) = reply {
+                    req = req.reply(*message);
+                }
+
+                req.exec().await.map_err(|e| e.to_string())?;
+                *reply = None;
+            },
+            Target::Interaction {
+                application,
+                token,
+                responded: false,
+            } => {
+                let client = client.interaction(*application);
+                // replaces all of them, e.g. when paging
+                let mut req = client
+                    .update_response(token)
+                    .content(part.content.as_deref())
+                    .map_err(|e| e.to_string())?
+                    .embeds(Some(&part.embeds))
+                    .map_err(|e| e.to_string())?
+                    .components(Some(&part.components))
+                    .map_err(|e| e.to_string())?;
+                if !part.attachments.is_empty() {
+                    req = req
+                        .attachments(&part.attachments)
+                        .map_err(|e| e.to_string())?;
+                }
+
+                req.exec().await.map_err(|e| e.to_string())?;
+                if let Target::Interaction { responded, .. } = target {
+                    *responded = true;
+                }
+            },
+            Target::Interaction {
+                application,
+                token,
+                responded: true,
+            } => {
+                let client = client.interaction(*application);
+                let mut req = client.create_followup(token);
+                if let Some(content) = &part.content {
+                    req = req.content(content).map_err(|e| e.to_string())?;
+                }
+                if !part.embeds.is_empty() {
+                    req = req.embeds(&part.embeds).map_err(|e| e.to_string())?;
+                }
+                if !part.attachments.is_empty() {
+                    req = req
+                        .attachments(&part.attachments)
+                        .map_err(|e| e.to_string())?;
+                }
+                if !part.components.is_empty() {
+                    req = req
+                        .components(&part.components)
+                        .map_err(|e| e.to_string())?;
+                }
+
+                req.exec().await.map_err(|e| e.to_string())?;
+            },
+        }
+
+        Ok(())
+    }
+}
+impl Handler<RegisterInteraction> for Responder {
+    type Result = ();
+
     fn handle(
         &mut self,
-        Reply {
-            msg,
-            to: MessageRef { message, channel },
-        }: Reply,
-        ctx: &mut Self::Context,
+        RegisterInteraction {
+            id,
+            application,
+            token,
+        }: RegisterInteraction,
+        _: &mut Self::Context,
     ) -> Self::Result {
-        self.client
-            .create_message(channel.try_into()?)
-            .content(&msg)
-            .expect("illegal message")
-            .reply(message.try_into()?)
-            .exec()
-            .pipe(|f| async {
-                match f.await {
-                    Ok(_) => (),
-                    Err(e) => tracing::error!("failed sending response: {}", e),
-                };
-            })
-            .into_actor(self)
-            .spawn(ctx);
+        self.interactions
+            .retain(|_, t| t.registered_at.elapsed() < INTERACTION_TTL);
 
-        Ok(())
+        self.interactions.insert(id, InteractionToken {
+            application,
+            token,
+            responded: false,
+            registered_at: Instant::now(),
+        });
     }
 }
 impl Supervised for Responder {}
@@ -112,9 +336,327 @@ impl ArbiterService for Responder {}
 
 #[derive(Debug, Clone)]
 pub struct Reply {
-    pub msg: String,
+    pub payload: Payload,
     pub to: MessageRef,
 }
 impl Message for Reply {
     type Result = Result<(), core::num::TryFromIntError>;
 }
+
+/// where to send parts of a reply
+enum Target {
+    Message {
+        channel: Id<ChannelMarker>,
+        reply: Option<Id<MessageMarker>>,
+    },
+    Interaction {
+        application: Id<ApplicationMarker>,
+        token: String,
+        responded: bool,
+    },
+}
+
+/// a message to send
+#[derive(Default)]
+struct Part {
+    content: Option<String>,
+    embeds: Vec<Embed>,
+    attachments: Vec<HttpAttachment>,
+    components: Vec<Component>,
+}
+
+/// splits at line boundaries, or attaches as a file if too many parts
+fn text_parts(text: &str) -> Vec<Part> {
+    let chunks = split_content(text, CONTENT_LIMIT);
+
+    if chunks.len() <= PARTS_LIMIT {
+        return chunks
+            .into_iter()
+            .map(|c| Part {
+                content: Some(c),
+                ..Default::default()
+            })
+            .collect();
+    }
+
+    let file = HttpAttachment::from_bytes("reply.txt".to_string(), text.as_bytes().to_vec(), 0);
+    vec![Part {
+        content: Some("too long, attached as a file".to_string()),
+        attachments: vec![file],
+        ..Default::default()
+    }]
+}
+
+/// chunks of at most `limit` characters, split at line boundaries if possible
+fn split_content(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut buf = String::new();
+    let mut len = 0;
+    // whether `buf` has a line, which may be empty
+    let mut started = false;
+
+    for line in text.lines() {
+        let line_len = line.chars().count();
+
+        // separated by a newline
+        if started && len + 1 + line_len <= limit {
+            buf.push('\n');
+            buf.push_str(line);
+            len += 1 + line_len;
+            continue;
+        }
+        if started {
+            chunks.push(core::mem::take(&mut buf));
+        }
+
+        // too long line is split at character boundaries
+        let mut chars = line.chars().peekable();
+        len = 0;
+        started = true;
+        while chars.peek().is_some() {
+            buf = chars.by_ref().take(limit).collect();
+            len = buf.chars().count();
+            if chars.peek().is_some() {
+                chunks.push(core::mem::take(&mut buf));
+            }
+        }
+    }
+    if started {
+        chunks.push(buf);
+    }
+
+    // empty content is rejected, so chunks of only blank lines are dropped
+    chunks.retain(|c| !c.trim().is_empty());
+    if chunks.is_empty() {
+        chunks.push("(empty)".to_string());
+    }
+
+    chunks
+}
+
+struct InteractionToken {
+    application: Id<ApplicationMarker>,
+    token: String,
+    responded: bool,
+    registered_at: Instant,
+}
+
+/// registers the token of deferred interaction, to reply to it
+pub struct RegisterInteraction {
+    pub id: u64,
+    pub application: Id<ApplicationMarker>,
+    pub token: String,
+}
+impl Message for RegisterInteraction {
+    type Result = ();
+}
+
+#[derive(Debug, Clone)]
+pub enum Payload {
+    Text(String),
+    Embed(EmbedData),
+}
+impl Payload {
+    /// `None` if text, or failed building
+    fn embed(&self) -> Option<Embed> {
+        let data = match self {
+            Payload::Text(_) => return None,
+            Payload::Embed(d) => d,
+        };
+
+        match data.build() {
+            Ok(o) => Some(o),
+            Err(e) => {
+                tracing::warn!("failed building embed: {}", e);
+                None
+            },
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Payload::Text(t) => t.clone(),
+            Payload::Embed(d) => d.to_text(),
+        }
+    }
+
+    /// buttons in a row, only with embeds
+    fn components(&self) -> Vec<Component> {
+        let buttons = match self {
+            Payload::Embed(d) if !d.buttons.is_empty() => &d.buttons,
+            _ => return vec![],
+        };
+
+        let buttons = buttons
+            .iter()
+            .map(|b| {
+                Component::Button(Button {
+                    custom_id: Some(b.custom_id.clone()),
+                    disabled: b.disabled,
+                    emoji: None,
+                    label: Some(b.label.clone()),
+                    style: ButtonStyle::Secondary,
+                    url: None,
+                })
+            })
+            .collect();
+
+        vec![Component::ActionRow(ActionRow {
+            components: buttons,
+        })]
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EmbedData {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub fields: Vec<EmbedField>,
+    pub color: Option<u32>,
+    pub footer: Option<String>,
+    pub thumbnail: Option<String>,
+    pub buttons: Vec<ButtonData>,
+}
+impl EmbedData {
+    fn build(&self) -> Result<Embed, String> {
+        let mut builder = EmbedBuilder::new();
+
+        if let Some(title) = &self.title {
+            builder = builder.title(title);
+        }
+        if let Some(description) = &self.description {
+            builder = builder.description(description);
+        }
+        for EmbedField {
+            name,
+            value,
+            inline,
+        } in &self.fields
+        {
+            builder = match inline {
+                true => builder.field(EmbedFieldBuilder::new(name, value).inline()),
+                false => builder.field(EmbedFieldBuilder::new(name, value)),
+            };
+        }
+        if let Some(color) = self.color {
+            builder = builder.color(color);
+        }
+        if let Some(footer) = &self.footer {
+            builder = builder.footer(EmbedFooterBuilder::new(footer));
+        }
+        if let Some(thumbnail) = &self.thumbnail {
+            builder = builder.thumbnail(ImageSource::url(thumbnail).map_err(|e| e.to_string())?);
+        }
+
+        builder
+            .validate()
+            .map_err(|e| e.to_string())?
+            .build()
+            .pipe(Ok)
+    }
+
+    /// fallback of the embed
+    fn to_text(&self) -> String {
+        let mut lines = vec![];
+
+        if let Some(title) = &self.title {
+            lines.push(format!("**{}**", title));
+        }
+        if let Some(description) = &self.description {
+            lines.push(description.clone());
+        }
+        for EmbedField { name, value, .. } in &self.fields {
+            lines.push(format!("{}: {}", name, value));
+        }
+        if let Some(thumbnail) = &self.thumbnail {
+            lines.push(format!("thumbnail: <{}>", thumbnail));
+        }
+        if let Some(footer) = &self.footer {
+            lines.push(footer.clone());
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// `custom_id` is received by `Interactor` when clicked
+#[derive(Debug, Clone)]
+pub struct ButtonData {
+    pub custom_id: String,
+    pub label: String,
+    pub disabled: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    pub inline: bool,
+}
+impl EmbedField {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            inline: false,
+        }
+    }
+
+    pub fn inline(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            inline: true,
+            ..Self::new(name, value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embed_falls_back_to_text() {
+        let mut embed = EmbedData {
+            title: Some("title".to_string()),
+            description: Some("description".to_string()),
+            fields: vec![EmbedField::inline("name", "value")],
+            ..Default::default()
+        };
+        assert!(Payload::Embed(embed.clone()).embed().is_some());
+        assert_eq!(
+            Payload::Embed(embed.clone()).text(),
+            "**title**\ndescription\nname: value"
+        );
+
+        // over the limit of titles
+        embed.title = Some("t".repeat(257));
+        assert!(Payload::Embed(embed).embed().is_none());
+    }
+
+    #[test]
+    fn split_content_at_lines() {
+        assert_eq!(split_content("", 10), vec!["(empty)"]);
+        assert_eq!(split_content("abc\ndef", 10), vec!["abc\ndef"]);
+        assert_eq!(split_content("abc\ndef\nghi", 7), vec!["abc\ndef", "ghi"]);
+
+        // blank lines at the start of the text or of a chunk are kept
+        assert_eq!(split_content("\nabc", 10), vec!["\nabc"]);
+        assert_eq!(split_content("abc\n\ndef", 5), vec!["abc\n", "def"]);
+        assert_eq!(split_content("abc\n\nde", 3), vec!["abc", "\nde"]);
+        assert_eq!(split_content("\n\n", 10), vec!["(empty)"]);
+
+        // too long line
+        assert_eq!(split_content("ab\nあいうえおか\nc", 4), vec![
+            "ab",
+            "あいうえ",
+            "おか\nc"
+        ]);
+
+        let long = "line\n".repeat(2000);
+        split_content(&long, CONTENT_LIMIT)
+            .iter()
+            .for_each(|c| assert!(c.chars().count() <= CONTENT_LIMIT));
+        assert_eq!(text_parts(&long).len(), 1);
+        assert_eq!(text_parts(&long)[0].attachments.len(), 1);
+    }
+}
@@ -0,0 +1,530 @@
+use alloc::sync::Arc;
+
+use actix::prelude::{
+    Actor, ArbiterService, Context, ContextFutureSpawner, Handler, Message, Supervised, WrapFuture,
+};
+use clap::{Arg, Command as ClapCommand};
+use twilight_http::Client;
+use twilight_model::application::command::{
+    Command, CommandOption, CommandOptionChoice, CommandType,
+};
+use twilight_model::application::interaction::application_command::{
+    CommandData, CommandDataOption, CommandOptionValue,
+};
+use twilight_model::application::interaction::{Interaction, InteractionData, InteractionType};
+use twilight_model::http::interaction::{InteractionResponse, InteractionResponseType};
+use twilight_util::builder::command::{
+    AttachmentBuilder, BooleanBuilder, CommandBuilder, IntegerBuilder, StringBuilder,
+    SubCommandBuilder,
+};
+use twilight_util::builder::InteractionResponseDataBuilder;
+
+use crate::command::{self, CommandParser, InteractionCommand};
+use crate::connection::{Connector, GetQueueTitles};
+use crate::gateway::{Attachment, MessageRef, RegisterInteraction, Responder};
+use crate::util::{reply_err, token, Pipe};
+
+/// arguments taking an index of the queue, as (command, arg)
+const QUEUE_INDEX_ARGS: &[(&str, &str)] = &[
+    ("slide", "from"),
+    ("slide", "to"),
+    ("drop", "items"),
+    ("skip-to", "index"),
+    ("loop", "index"),
+];
+
+/// commands accepting attachments
+const ATTACHMENT_COMMANDS: &[&str] = &["repo upload"];
+const ATTACHMENT_OPTION: &str = "attachment";
+
+const IGNORED: &[&str] = &["help", "version"];
+
+// limits by discord
+const DESCRIPTION_LIMIT: usize = 100;
+const CHOICES_LIMIT: usize = 25;
+
+/// registers all of `GuildCommand` as global application commands
+pub async fn register_commands() {
+    let client = Client::new(token());
+
+    let result: Result<_, String> = try {
+        let application = client
+            .current_user_application()
+            .exec()
+            .await
+            .map_err(|e| e.to_string())?
+            .model()
+            .await
+            .map_err(|e| e.to_string())?
+            .id;
+
+        client
+            .interaction(application)
+            .set_global_commands(&commands())
+            .exec()
+            .await
+            .map_err(|e| e.to_string())?;
+    };
+
+    match result {
+        Ok(()) => tracing::info!("registered application commands"),
+        Err(e) => tracing::error!("failed registering application commands: {}", e),
+    }
+}
+
+/// builds application commands from the definition of `GuildCommand`
+pub fn commands() -> Vec<Command> {
+    let mut root = command::guild_command();
+    root.build();
+
+    subcommands(&root)
+        .map(|cmd| {
+            let name = cmd.get_name();
+            let builder = CommandBuilder::new(
+                name,
+                describe(cmd.get_about(), name),
+                CommandType::ChatInput,
+            )
+            .dm_permission(false);
+
+            match cmd.has_subcommands() {
+                true => subcommands(cmd).fold(builder, |b, sub| {
+                    let path = format!("{} {}", name, sub.get_name());
+                    let sub = options(&path, sub).into_iter().fold(
+                        SubCommandBuilder::new(sub.get_name(), describe(sub.get_about(), &path)),
+                        |b, o| b.option(o),
+                    );
+
+                    b.option(sub.build())
+                }),
+                false => options(name, cmd)
+                    .into_iter()
+                    .fold(builder, |b, o| b.option(o)),
+            }
+            .build()
+        })
+        .collect()
+}
+
+fn subcommands<'a>(
+    cmd: &'a ClapCommand<'static>,
+) -> impl Iterator<Item = &'a ClapCommand<'static>> {
+    cmd.get_subcommands()
+        .filter(|c| !IGNORED.contains(&c.get_name()))
+}
+
+fn options(path: &str, cmd: &ClapCommand) -> Vec<CommandOption> {
+    let mut args = cmd
+        .get_arguments()
+        .filter(|a| !IGNORED.contains(&a.get_id()))
+        .collect::<Vec<_>>();
+
+    // discord requires required options first
+    args.sort_by_key(|a| !a.is_required_set());
+
+    let mut options = args
+        .into_iter()
+        .map(|a| option(path, a))
+        .collect::<Vec<_>>();
+
+    if ATTACHMENT_COMMANDS.contains(&path) {
+        AttachmentBuilder::new(ATTACHMENT_OPTION, "file to upload")
+            .build()
+            .pipe(|o| options.push(o));
+    }
+
+    options
+}
+
+fn option(path: &str, arg: &Arg) -> CommandOption {
+    let name = arg.get_id();
+    let description = describe(arg.get_help(), name);
+
+    if !arg.is_takes_value_set() {
+        return BooleanBuilder::new(name, description).build();
+    }
+
+    if QUEUE_INDEX_ARGS.contains(&(path, name)) {
+        return IntegerBuilder::new(name, description)
+            .required(arg.is_required_set())
+            .min_value(0)
+            .autocomplete(true)
+            .build();
+    }
+
+    StringBuilder::new(name, description)
+        .required(arg.is_required_set())
+        .build()
+}
+
+fn describe(text: Option<&str>, fallback: &str) -> String {
+    let text = text.and_then(|t| t.lines().next()).unwrap_or(fallback);
+
+    text.chars().take(DESCRIPTION_LIMIT).collect()
+}
+
+/// converts options into the arguments of `GuildCommand` (without binary
+/// name), with attachments
+pub fn to_args(data: &CommandData) -> Result<(Vec<String>, Vec<Attachment>), String> {
+    let mut root = command::guild_command();
+    root.build();
+
+    let mut cmd = root
+        .find_subcommand(&data.name)
+        .ok_or_else(|| format!("unknown command: {}", data.name))?;
+    let mut args = vec![data.name.clone()];
+
+    let mut options = &data.options;
+    if let [CommandDataOption {
+        name,
+        value: CommandOptionValue::SubCommand(sub),
+    }] = options.as_slice()
+    {
+        cmd = cmd
+            .find_subcommand(name)
+            .ok_or_else(|| format!("unknown command: {} {}", data.name, name))?;
+        args.push(name.clone());
+        options = sub;
+    }
+
+    let mut attachments = vec![];
+    let mut positionals = vec![];
+    for CommandDataOption { name, value } in options {
+        if name == ATTACHMENT_OPTION {
+            let attachment = match value {
+                CommandOptionValue::Attachment(id) => data
+                    .resolved
+                    .as_ref()
+                    .and_then(|r| r.attachments.get(id))
+                    .ok_or_else(|| "unresolved attachment".to_string())?,
+                _ => return "illegal attachment".to_string().pipe(Err),
+            };
+
+            attachments.push(attachment.clone().into());
+            continue;
+        }
+
+        let arg = cmd
+            .get_arguments()
+            .find(|a| a.get_id() == name)
+            .ok_or_else(|| format!("unknown option: {}", name))?;
+
+        let value = match value {
+            CommandOptionValue::Boolean(true) => None,
+            CommandOptionValue::Boolean(false) => continue,
+            CommandOptionValue::Integer(i) => i.to_string().pipe(Some),
+            CommandOptionValue::String(s) => s.clone().pipe(Some),
+            v => return format!("unsupported option: {:?}", v.kind()).pipe(Err),
+        };
+
+        match (arg.get_long(), value) {
+            (Some(long), value) => {
+                args.push(format!("--{}", long));
+                args.extend(value);
+            },
+            (None, Some(value)) => positionals.push((arg.get_index(), value)),
+            (None, None) => return format!("illegal option: {}", name).pipe(Err),
+        }
+    }
+
+    positionals.sort_by_key(|(i, _)| *i);
+    if !positionals.is_empty() {
+        // to pass values starting with hyphen as is
+        args.push("--".to_string());
+        args.extend(positionals.into_iter().map(|(_, v)| v));
+    }
+
+    (args, attachments).pipe(Ok)
+}
+
+/// returns (command, arg, typed value) of focused option
+fn focused(data: &CommandData) -> Option<(String, &str, &str)> {
+    let mut path = data.name.clone();
+    let mut options = &data.options;
+
+    if let [CommandDataOption {
+        name,
+        value: CommandOptionValue::SubCommand(sub),
+    }] = options.as_slice()
+    {
+        path = format!("{} {}", path, name);
+        options = sub;
+    }
+
+    options.iter().find_map(|o| match &o.value {
+        CommandOptionValue::Focused(typed, _) =>
+            Some((path.clone(), o.name.as_str(), typed.as_str())),
+        _ => None,
+    })
+}
+
+pub struct Interactor {
+    client: Arc<Client>,
+}
+impl Default for Interactor {
+    fn default() -> Self {
+        Self {
+            client: Client::new(token()).pipe(Arc::new),
+        }
+    }
+}
+impl Actor for Interactor {
+    type Context = Context<Self>;
+}
+impl Handler<InteractionReceived> for Interactor {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        InteractionReceived(interaction): InteractionReceived,
+        ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let client = self.client.clone();
+
+        async move {
+            let result = match interaction.kind {
+                InteractionType::ApplicationCommand | InteractionType::MessageComponent =>
+                    Self::command(client, interaction).await,
+                InteractionType::ApplicationCommandAutocomplete =>
+                    Self::autocomplete(client, interaction).await,
+                _ => Ok(()),
+            };
+
+            if let Err(e) = result {
+                tracing::error!("failed handling interaction: {}", e);
+            }
+        }
+        .into_actor(self)
+        .spawn(ctx);
+    }
+}
+impl Interactor {
+    /// components run commands as well, replacing the message
+    async fn command(client: Arc<Client>, interaction: Interaction) -> Result<(), String> {
+        let Interaction {
+            id,
+            application_id,
+            data,
+            guild_id,
+            channel_id,
+            token,
+            ..
+        } = &interaction;
+
+        let (guild, channel, user) = match (guild_id, channel_id, interaction.author_id()) {
+            (Some(g), Some(c), Some(u)) => (g.get(), c.get(), u.get()),
+            _ =>
+                return "interaction without guild, channel or user"
+                    .to_string()
+                    .pipe(Err),
+        };
+        let roles = interaction
+            .member
+            .as_ref()
+            .map(|m| m.roles.iter().map(|r| r.get()).collect());
+        let (kind, parsed) = match data {
+            Some(InteractionData::ApplicationCommand(d)) => (
+                InteractionResponseType::DeferredChannelMessageWithSource,
+                to_args(d),
+            ),
+            Some(InteractionData::MessageComponent(d)) =>
+                match command::page_button_args(&d.custom_id) {
+                    Some(args) => (
+                        InteractionResponseType::DeferredUpdateMessage,
+                        Ok((args, vec![])),
+                    ),
+                    None => return format!("unknown component: {}", d.custom_id).pipe(Err),
+                },
+            _ => return "interaction without command data".to_string().pipe(Err),
+        };
+
+        // commands may take over 3 seconds, so respond later
+        let response = InteractionResponse { kind, data: None };
+        client
+            .interaction(*application_id)
+            .create_response(*id, token, &response)
+            .exec()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Responder::from_registry()
+            .try_send(RegisterInteraction {
+                id: id.get(),
+                application: *application_id,
+                token: token.clone(),
+            })
+            .expect("failed sending");
+
+        let from = MessageRef::Interaction {
+            id: id.get(),
+            channel,
+        };
+        match parsed {
+            Ok((args, attachments)) => CommandParser::from_registry()
+                .try_send(InteractionCommand {
+                    args,
+                    attachments,
+                    user,
+                    guild,
+                    roles,
+                    from,
+                })
+                .expect("failed sending"),
+            Err(e) => reply_err(e, from),
+        }
+
+        Ok(())
+    }
+
+    async fn autocomplete(client: Arc<Client>, interaction: Interaction) -> Result<(), String> {
+        let Interaction {
+            id,
+            application_id,
+            data,
+            guild_id,
+            token,
+            ..
+        } = &interaction;
+
+        let guild = match guild_id {
+            Some(g) => g.get(),
+            None => return "interaction without guild".to_string().pipe(Err),
+        };
+        let data = match data {
+            Some(InteractionData::ApplicationCommand(d)) => d,
+            _ => return "interaction without command data".to_string().pipe(Err),
+        };
+
+        let choices = match focused(data) {
+            Some((path, arg, typed)) if QUEUE_INDEX_ARGS.contains(&(path.as_str(), arg)) =>
+                Self::queue_choices(guild, typed).await,
+            _ => vec![],
+        };
+
+        let response = InteractionResponse {
+            kind: InteractionResponseType::ApplicationCommandAutocompleteResult,
+            data: InteractionResponseDataBuilder::new()
+                .choices(choices)
+                .build()
+                .pipe(Some),
+        };
+        client
+            .interaction(*application_id)
+            .create_response(*id, token, &response)
+            .exec()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    async fn queue_choices(guild: u64, typed: &str) -> Vec<CommandOptionChoice> {
+        let titles = Connector::from_registry()
+            .send(GetQueueTitles { guild })
+            .await
+            .expect("failed sending")
+            .unwrap_or_default();
+
+        let typed = typed.trim().to_lowercase();
+        titles
+            .into_iter()
+            .enumerate()
+            .filter(|(i, t)| i.to_string().starts_with(&typed) || t.to_lowercase().contains(&typed))
+            .take(CHOICES_LIMIT)
+            .map(|(i, t)| CommandOptionChoice::Int {
+                name: format!("{}: {}", i, t)
+                    .chars()
+                    .take(DESCRIPTION_LIMIT)
+                    .collect(),
+                name_localizations: None,
+                value: i as i64,
+            })
+            .collect()
+    }
+}
+impl Supervised for Interactor {}
+impl ArbiterService for Interactor {}
+
+pub struct InteractionReceived(pub Interaction);
+impl Message for InteractionReceived {
+    type Result = ();
+}
+
+#[cfg(test)]
+mod tests {
+    use twilight_model::id::Id;
+
+    use super::*;
+
+    fn data(name: &str, options: Vec<CommandDataOption>) -> CommandData {
+        CommandData {
+            guild_id: None,
+            id: Id::new(1),
+            name: name.to_string(),
+            kind: CommandType::ChatInput,
+            options,
+            resolved: None,
+            target_id: None,
+        }
+    }
+
+    fn option(name: &str, value: CommandOptionValue) -> CommandDataOption {
+        CommandDataOption {
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn commands_are_valid() {
+        let commands = commands();
+
+        assert!(commands.iter().any(|c| c.name == "enqueue"));
+        assert!(!commands.iter().any(|c| c.name == "help"));
+
+        for command in commands {
+            twilight_validate::command::command(&command).unwrap();
+        }
+    }
+
+    #[test]
+    fn to_args_converts_options() {
+        use CommandOptionValue::*;
+
+        let (args, _) =
+            to_args(&data("seek", vec![option("time", String("-10s".into()))])).unwrap();
+        assert_eq!(args, ["seek", "--", "-10s"]);
+
+        let (args, _) = to_args(&data("loop", vec![
+            option("times", Integer(3)),
+            option("index", Integer(2)),
+            option("off", Boolean(false)),
+        ]))
+        .unwrap();
+        assert_eq!(args, ["loop", "--times", "3", "--", "2"]);
+
+        let (args, _) = to_args(&data("repo", vec![option(
+            "visibility",
+            SubCommand(vec![
+                option("id", Integer(1)),
+                option("visibility", String("public".into())),
+            ]),
+        )]))
+        .unwrap();
+        assert_eq!(args, ["repo", "visibility", "--", "1", "public"]);
+
+        assert!(to_args(&data("unknown", vec![])).is_err());
+        assert!(to_args(&data("seek", vec![option("unknown", Integer(1))])).is_err());
+    }
+
+    #[test]
+    fn page_buttons_run_only_paged_commands() {
+        assert_eq!(
+            command::page_button_args("show-queue:next:3"),
+            Some(vec!["show-queue".to_string(), "3".to_string()])
+        );
+        assert_eq!(command::page_button_args("leave:next:3"), None);
+        assert_eq!(command::page_button_args("show-history:next:x"), None);
+        assert_eq!(command::page_button_args("show-history"), None);
+    }
+}
@@ -7,25 +7,56 @@
 
 extern crate alloc;
 
+mod announce;
 mod command;
+mod config;
 mod connection;
 mod gateway;
+mod interaction;
+mod permission;
+mod presence;
+mod repository;
+mod search;
+mod settings;
+mod source;
+mod storage;
+mod timespec;
 mod util;
+mod voice;
 
 use alloc::sync::Arc;
+use std::collections::HashSet;
 
 use actix::Registry;
-use connection::Connector;
+use command::CommandParser;
+use connection::{Connector, Restore};
 use gateway::{Gateway, GatewayMessage, MessageRef};
+use interaction::{InteractionReceived, Interactor};
+use permission::{GuildChanged, Permissions, RoleChanged, RoleInfo};
+use presence::Presence;
+use search::{Searcher, YoutubeSearch};
 use songbird::Songbird;
+use storage::FileStorage;
 use twilight_gateway::cluster::Events;
-use twilight_gateway::{Cluster, Event, Intents};
+use twilight_gateway::{Cluster, Event};
 use twilight_http::Client;
+use twilight_model::channel::{Channel, ChannelType};
+use twilight_model::guild::{Permissions as GuildPermissions, Role};
 use twilight_model::id::marker::UserMarker;
 use twilight_model::id::Id;
+use voice::{VoiceChannelChanged, VoiceStateChanged, VoiceStates};
 
 fn main() {
-    tracing_subscriber::fmt().pretty().init();
+    let loaded = config::load();
+    let level = config::config().log_level().unwrap_or(tracing::Level::INFO);
+    tracing_subscriber::fmt()
+        .pretty()
+        .with_max_level(level)
+        .init();
+
+    if let Err(e) = loaded {
+        tracing::error!("failed loading config, using defaults: {}", e);
+    }
 
     let sys = actix::System::new();
 
@@ -50,28 +81,172 @@ async fn initialize() {
 
     let songbird = Songbird::twilight(Arc::new(cluster), user_id).pipe(Arc::new);
 
-    let connector = Connector::new(songbird.clone()).start();
-    Registry::set(connector);
+    let storage = match FileStorage::new(util::data_dir()) {
+        Ok(o) => Arc::new(o),
+        Err(e) => {
+            tracing::error!("failed initializing storage: {}", e);
+            return actix::System::current().stop();
+        },
+    };
+
+    let connector = Connector::new(songbird.clone(), storage).start();
+    Registry::set(connector.clone());
+    Registry::set(Searcher::new(Arc::new(YoutubeSearch)).start());
+    Registry::set(Presence::new(songbird.clone()).start());
+    Registry::set(CommandParser::new(user_id.get()).start());
+
+    actix::spawn(interaction::register_commands());
 
     let gateway = Gateway::from_registry();
+    let interactor = Interactor::from_registry();
+    let voice_states = VoiceStates::from_registry();
+    let permissions = Permissions::from_registry();
 
     let fut = async move {
+        let mut restored = false;
+
         while let Some((id, event)) = events.next().await {
             tracing::trace!("received event: ({}) {:?}", id, event);
             songbird.process(&event).await;
 
-            if let Event::MessageCreate(mc) = event {
-                let msg = GatewayMessage {
-                    content: mc.0.content,
-                    from: MessageRef {
-                        message: mc.0.id.get(),
-                        channel: mc.0.channel_id.get(),
-                    },
-                    user: mc.0.author.id.get(),
-                    guild: mc.0.guild_id.map(|i| i.get()),
-                };
-
-                gateway.try_send(msg).expect("failed sending")
+            match event {
+                Event::Ready(_) if !restored => {
+                    restored = true;
+                    connector.try_send(Restore).expect("failed sending")
+                },
+                // ignores other bots and own replies, which may start with command names
+                Event::MessageCreate(mc) if mc.0.author.bot => (),
+                Event::MessageCreate(mc) => {
+                    let msg = GatewayMessage {
+                        content: mc.0.content,
+                        attachments: mc.0.attachments.into_iter().map(Into::into).collect(),
+                        from: MessageRef::Message {
+                            message: mc.0.id.get(),
+                            channel: mc.0.channel_id.get(),
+                        },
+                        user: mc.0.author.id.get(),
+                        guild: mc.0.guild_id.map(|i| i.get()),
+                        roles: mc
+                            .0
+                            .member
+                            .map(|m| m.roles.into_iter().map(|r| r.get()).collect()),
+                    };
+
+                    gateway.try_send(msg).expect("failed sending")
+                },
+                Event::InteractionCreate(ic) => interactor
+                    .try_send(InteractionReceived(ic.0))
+                    .expect("failed sending"),
+                Event::GuildCreate(gc) => {
+                    let guild = gc.0.id.get();
+
+                    permissions
+                        .try_send(GuildChanged {
+                            guild,
+                            owner: gc.0.owner_id.get(),
+                            roles: gc.0.roles.into_iter().map(role_info).collect(),
+                        })
+                        .expect("failed sending");
+
+                    gc.0.channels.into_iter().for_each(|c| {
+                        voice_states
+                            .try_send(voice_channel_changed(guild, c))
+                            .expect("failed sending")
+                    });
+
+                    let bots: HashSet<_> =
+                        gc.0.members
+                            .iter()
+                            .filter(|m| m.user.bot)
+                            .map(|m| m.user.id)
+                            .collect();
+
+                    gc.0.voice_states.into_iter().for_each(|vs| {
+                        voice_states
+                            .try_send(VoiceStateChanged {
+                                guild,
+                                user: vs.user_id.get(),
+                                channel: vs.channel_id.map(|i| i.get()),
+                                bot: bots.contains(&vs.user_id),
+                            })
+                            .expect("failed sending")
+                    })
+                },
+                Event::VoiceStateUpdate(vsu) => {
+                    let guild = match vsu.0.guild_id {
+                        Some(g) => g.get(),
+                        None => continue,
+                    };
+
+                    voice_states
+                        .try_send(VoiceStateChanged {
+                            guild,
+                            user: vsu.0.user_id.get(),
+                            channel: vsu.0.channel_id.map(|i| i.get()),
+                            bot: vsu.0.member.as_ref().is_some_and(|m| m.user.bot),
+                        })
+                        .expect("failed sending")
+                },
+                Event::GuildUpdate(gu) => permissions
+                    .try_send(GuildChanged {
+                        guild: gu.0.id.get(),
+                        owner: gu.0.owner_id.get(),
+                        roles: gu.0.roles.into_iter().map(role_info).collect(),
+                    })
+                    .expect("failed sending"),
+                Event::RoleCreate(rc) => permissions
+                    .try_send(RoleChanged {
+                        guild: rc.guild_id.get(),
+                        role: Ok(role_info(rc.role)),
+                    })
+                    .expect("failed sending"),
+                Event::RoleUpdate(ru) => permissions
+                    .try_send(RoleChanged {
+                        guild: ru.guild_id.get(),
+                        role: Ok(role_info(ru.role)),
+                    })
+                    .expect("failed sending"),
+                Event::RoleDelete(rd) => permissions
+                    .try_send(RoleChanged {
+                        guild: rd.guild_id.get(),
+                        role: Err(rd.role_id.get()),
+                    })
+                    .expect("failed sending"),
+                Event::ChannelCreate(cc) => {
+                    let guild = match cc.0.guild_id {
+                        Some(g) => g.get(),
+                        None => continue,
+                    };
+
+                    voice_states
+                        .try_send(voice_channel_changed(guild, cc.0))
+                        .expect("failed sending")
+                },
+                Event::ChannelUpdate(cu) => {
+                    let guild = match cu.0.guild_id {
+                        Some(g) => g.get(),
+                        None => continue,
+                    };
+
+                    voice_states
+                        .try_send(voice_channel_changed(guild, cu.0))
+                        .expect("failed sending")
+                },
+                Event::ChannelDelete(cd) => {
+                    let guild = match cd.0.guild_id {
+                        Some(g) => g.get(),
+                        None => continue,
+                    };
+
+                    voice_states
+                        .try_send(VoiceChannelChanged {
+                            guild,
+                            channel: cd.0.id.get(),
+                            name: None,
+                        })
+                        .expect("failed sending")
+                },
+                _ => (),
             }
         }
     };
@@ -79,17 +254,32 @@ async fn initialize() {
     tokio::spawn(fut);
 }
 
+fn voice_channel_changed(guild: u64, channel: Channel) -> VoiceChannelChanged {
+    let is_voice = matches!(
+        channel.kind,
+        ChannelType::GuildVoice | ChannelType::GuildStageVoice
+    );
+
+    VoiceChannelChanged {
+        guild,
+        channel: channel.id.get(),
+        name: channel.name.filter(|_| is_voice),
+    }
+}
+
+fn role_info(role: Role) -> RoleInfo {
+    RoleInfo {
+        id: role.id.get(),
+        name: role.name,
+        admin: role.permissions.contains(GuildPermissions::ADMINISTRATOR),
+    }
+}
+
 async fn build_cluster() -> (Cluster, Id<UserMarker>, Events) {
+    let intents = config::config().intents().expect("validated on loading");
+
     let (cluster, events) = loop {
-        match Cluster::new(
-            util::token::<String>(),
-            Intents::GUILD_VOICE_STATES
-                | Intents::GUILD_MESSAGES
-                | Intents::DIRECT_MESSAGES
-                | Intents::MESSAGE_CONTENT,
-        )
-        .await
-        {
+        match Cluster::new(util::token::<String>(), intents).await {
             Ok(t) => break t,
             Err(e) => tracing::warn!("failed initializing cluster: {}", e),
         }
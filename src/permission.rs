@@ -0,0 +1,512 @@
+use alloc::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use actix::prelude::{
+    Actor, ActorFutureExt, ArbiterService, Context, Handler, Message, ResponseActFuture,
+    Supervised, WrapFuture,
+};
+use serde::{Deserialize, Serialize};
+use twilight_http::Client;
+use twilight_model::id::Id;
+
+use crate::settings::{GetGuildSettings, SetPermissions, Settings};
+use crate::util::{token, Pipe};
+
+/// commands only administrators can run, changing how the bot works in the
+/// guild
+const ADMIN_COMMANDS: &[&str] = &["permission", "config", "alias"];
+
+/// commands requiring the DJ role, if set
+///
+/// commands only enqueueing (play, replay, requeue-history, ...) are open to
+/// everyone, as they do not interrupt the current track
+const DJ_COMMANDS: &[&str] = &[
+    "leave",
+    "stop",
+    "drop",
+    "skip",
+    "skip-to",
+    "previous",
+    "slide",
+    "shuffle",
+    "loop",
+    "seek",
+    "pause",
+    "resume",
+    "volume",
+    "volume-current",
+    "fix",
+    "policy",
+    "announce",
+    "page-size",
+];
+
+/// roles of a guild to resolve, and who bypasses checks
+#[derive(Default)]
+struct GuildRoles {
+    owner: u64,
+    names: HashMap<u64, String>,
+    // roles with ADMINISTRATOR
+    admins: HashSet<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    pub allow: BTreeSet<u64>,
+    pub deny: BTreeSet<u64>,
+}
+
+/// saved with settings of the guild
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionSettings {
+    pub dj_role: Option<u64>,
+    /// command -> rule
+    pub rules: BTreeMap<String, Rule>,
+}
+impl PermissionSettings {
+    pub fn apply(&mut self, update: PermissionUpdate) {
+        use PermissionUpdate::*;
+        match update {
+            DjRole(role) => self.dj_role = role,
+            Allow { command, role } => {
+                let rule = self.rules.entry(command).or_default();
+                rule.deny.remove(&role);
+                rule.allow.insert(role);
+            },
+            Deny { command, role } => {
+                let rule = self.rules.entry(command).or_default();
+                rule.allow.remove(&role);
+                rule.deny.insert(role);
+            },
+            Clear { command } => {
+                self.rules.remove(&command);
+            },
+        }
+    }
+}
+
+/// what the user may do with the command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// administrators, or members with the DJ role
+    Privileged,
+    All,
+    /// only on tracks the user enqueued
+    OwnTracks,
+}
+
+/// checks commands against roles of the user, per guild
+///
+/// settings are owned by `Settings`, only roles are kept here
+pub struct Permissions {
+    client: Arc<Client>,
+    roles: HashMap<u64, GuildRoles>,
+}
+impl Permissions {
+    fn role_name(&self, guild: u64, role: u64) -> String {
+        role_name(self.roles.get(&guild).map(|r| &r.names), guild, role)
+    }
+
+    fn check(
+        &self,
+        guild: u64,
+        user: u64,
+        roles: &[u64],
+        settings: &PermissionSettings,
+        command: &str,
+    ) -> Result<Access, String> {
+        let admin = self
+            .roles
+            .get(&guild)
+            .is_some_and(|g| g.owner == user || roles.iter().any(|r| g.admins.contains(r)));
+        if admin {
+            return Ok(Access::Privileged);
+        }
+
+        if ADMIN_COMMANDS.contains(&command) {
+            return format!("denied: \"{}\" requires administrator", command).pipe(Err);
+        }
+
+        // everyone has the role of @everyone, whose id is the guild id
+        let has = |role: &u64| *role == guild || roles.contains(role);
+
+        if let Some(rule) = settings.rules.get(command) {
+            if let Some(role) = rule.deny.iter().find(|r| has(r)) {
+                return format!(
+                    "denied: \"{}\" is denied for {}",
+                    command,
+                    self.role_name(guild, *role)
+                )
+                .pipe(Err);
+            }
+
+            if !rule.allow.is_empty() && !rule.allow.iter().any(has) {
+                let allowed = rule
+                    .allow
+                    .iter()
+                    .map(|r| self.role_name(guild, *r))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return format!("denied: \"{}\" is only allowed for {}", command, allowed)
+                    .pipe(Err);
+            }
+        }
+
+        match settings.dj_role {
+            Some(dj) if has(&dj) => Ok(Access::Privileged),
+            Some(dj) if DJ_COMMANDS.contains(&command) => match command {
+                "drop" | "skip" | "skip-to" => Ok(Access::OwnTracks),
+                _ => format!(
+                    "denied: \"{}\" requires the DJ role ({})",
+                    command,
+                    self.role_name(guild, dj)
+                )
+                .pipe(Err),
+            },
+            _ => Ok(Access::All),
+        }
+    }
+}
+impl Default for Permissions {
+    fn default() -> Self {
+        Self {
+            client: Client::new(token()).pipe(Arc::new),
+            roles: HashMap::new(),
+        }
+    }
+}
+impl Actor for Permissions {
+    type Context = Context<Self>;
+}
+impl Handler<GuildChanged> for Permissions {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        GuildChanged {
+            guild,
+            owner,
+            roles,
+        }: GuildChanged,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let entry = self.roles.entry(guild).or_default();
+        entry.owner = owner;
+        entry.names.clear();
+        entry.admins.clear();
+
+        for RoleInfo { id, name, admin } in roles {
+            entry.names.insert(id, name);
+            if admin {
+                entry.admins.insert(id);
+            }
+        }
+    }
+}
+impl Handler<RoleChanged> for Permissions {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        RoleChanged { guild, role }: RoleChanged,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let entry = self.roles.entry(guild).or_default();
+
+        match role {
+            Ok(RoleInfo { id, name, admin }) => {
+                entry.names.insert(id, name);
+                match admin {
+                    true => entry.admins.insert(id),
+                    false => entry.admins.remove(&id),
+                };
+            },
+            Err(id) => {
+                entry.names.remove(&id);
+                entry.admins.remove(&id);
+            },
+        }
+    }
+}
+impl Handler<CheckPermission> for Permissions {
+    type Result = ResponseActFuture<Self, Result<Access, String>>;
+
+    fn handle(
+        &mut self,
+        CheckPermission {
+            guild,
+            user,
+            roles,
+            command,
+        }: CheckPermission,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let client = self.client.clone();
+
+        async move {
+            let settings = Settings::from_registry()
+                .send(GetGuildSettings { guild })
+                .await
+                .expect("failed sending")
+                .permissions;
+
+            // commands from direct messages do not carry roles
+            let roles: Result<_, String> = try {
+                match roles {
+                    Some(r) => r,
+                    None => client
+                        .guild_member(Id::new(guild), Id::new(user))
+                        .exec()
+                        .await
+                        .map_err(|e| e.to_string())?
+                        .model()
+                        .await
+                        .map_err(|e| e.to_string())?
+                        .roles
+                        .into_iter()
+                        .map(|r| r.get())
+                        .collect::<Vec<_>>(),
+                }
+            };
+
+            (roles, settings)
+        }
+        .into_actor(self)
+        .map(move |(roles, settings), this, _| {
+            let roles = roles.map_err(|e| format!("failed getting roles: {}", e))?;
+            this.check(guild, user, &roles, &settings, &command)
+        })
+        .pipe(Box::pin)
+    }
+}
+impl Handler<ResolveRole> for Permissions {
+    type Result = Result<u64, String>;
+
+    fn handle(
+        &mut self,
+        ResolveRole { guild, query }: ResolveRole,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        if query == "everyone" || query == "@everyone" {
+            return Ok(guild);
+        }
+
+        let names = self.roles.get(&guild).map(|r| &r.names);
+
+        let id = query
+            .strip_prefix("<@&")
+            .and_then(|q| q.strip_suffix('>'))
+            .unwrap_or(&query);
+        if let Ok(id) = id.parse() {
+            return match names {
+                // roles may be unknown before receiving GuildCreate
+                Some(n) if !n.is_empty() && !n.contains_key(&id) =>
+                    format!("no such role: {}", id).pipe(Err),
+                _ => Ok(id),
+            };
+        }
+
+        let query = query.strip_prefix('@').unwrap_or(&query);
+        let mut found = names
+            .into_iter()
+            .flatten()
+            .filter(|(_, n)| n.eq_ignore_ascii_case(query))
+            .map(|(r, _)| *r);
+
+        match (found.next(), found.next()) {
+            (Some(r), None) => Ok(r),
+            (Some(_), Some(_)) => format!("ambiguous role name: {}", query).pipe(Err),
+            (None, _) => format!("no such role: {}", query).pipe(Err),
+        }
+    }
+}
+impl Handler<UpdatePermissions> for Permissions {
+    type Result = ResponseActFuture<Self, Result<PermissionsStatus, String>>;
+
+    fn handle(
+        &mut self,
+        UpdatePermissions { guild, update }: UpdatePermissions,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        async move {
+            let settings = Settings::from_registry();
+            match update {
+                Some(update) => settings
+                    .send(SetPermissions { guild, update })
+                    .await
+                    .expect("failed sending")?,
+                None => settings
+                    .send(GetGuildSettings { guild })
+                    .await
+                    .expect("failed sending"),
+            }
+            .permissions
+            .pipe(Ok)
+        }
+        .into_actor(self)
+        .map(move |settings: Result<_, String>, this, _| {
+            PermissionsStatus {
+                guild,
+                settings: settings?,
+                names: this
+                    .roles
+                    .get(&guild)
+                    .map(|r| r.names.clone())
+                    .unwrap_or_default(),
+            }
+            .pipe(Ok)
+        })
+        .pipe(Box::pin)
+    }
+}
+impl Supervised for Permissions {}
+impl ArbiterService for Permissions {}
+
+/// "@name" of the role, or its id if unknown
+pub fn role_name(names: Option<&HashMap<u64, String>>, guild: u64, role: u64) -> String {
+    if role == guild {
+        return "@everyone".to_string();
+    }
+
+    match names.and_then(|n| n.get(&role)) {
+        Some(name) => format!("@{}", name),
+        None => format!("role {}", role),
+    }
+}
+
+pub struct RoleInfo {
+    pub id: u64,
+    pub name: String,
+    /// has ADMINISTRATOR
+    pub admin: bool,
+}
+
+/// replaces the owner and all roles of the guild
+pub struct GuildChanged {
+    pub guild: u64,
+    pub owner: u64,
+    pub roles: Vec<RoleInfo>,
+}
+impl Message for GuildChanged {
+    type Result = ();
+}
+
+/// `Err` with the id if deleted
+pub struct RoleChanged {
+    pub guild: u64,
+    pub role: Result<RoleInfo, u64>,
+}
+impl Message for RoleChanged {
+    type Result = ();
+}
+
+/// `roles` are fetched if `None`
+pub struct CheckPermission {
+    pub guild: u64,
+    pub user: u64,
+    pub roles: Option<Vec<u64>>,
+    pub command: String,
+}
+impl Message for CheckPermission {
+    type Result = Result<Access, String>;
+}
+
+/// accepts an id, a mention, a name or "everyone"
+pub struct ResolveRole {
+    pub guild: u64,
+    pub query: String,
+}
+impl Message for ResolveRole {
+    type Result = Result<u64, String>;
+}
+
+pub enum PermissionUpdate {
+    /// `None` disables DJ mode
+    DjRole(Option<u64>),
+    /// allows the role, and removes it from denied
+    Allow { command: String, role: u64 },
+    /// denies the role, and removes it from allowed
+    Deny { command: String, role: u64 },
+    /// removes the rule of the command
+    Clear { command: String },
+}
+
+/// `None` only shows, and returns updated settings
+pub struct UpdatePermissions {
+    pub guild: u64,
+    pub update: Option<PermissionUpdate>,
+}
+impl Message for UpdatePermissions {
+    type Result = Result<PermissionsStatus, String>;
+}
+
+pub struct PermissionsStatus {
+    pub guild: u64,
+    pub settings: PermissionSettings,
+    pub names: HashMap<u64, String>,
+}
+impl PermissionsStatus {
+    pub fn role_name(&self, role: u64) -> String { role_name(Some(&self.names), self.guild, role) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: u64 = 1;
+    const OWNER: u64 = 10;
+    const ADMIN: u64 = 100;
+    const DJ: u64 = 200;
+    const MUTED: u64 = 300;
+
+    fn permissions() -> (Permissions, PermissionSettings) {
+        let mut names = HashMap::new();
+        names.insert(ADMIN, "admin".to_string());
+        names.insert(DJ, "dj".to_string());
+
+        let mut roles = HashMap::new();
+        roles.insert(GUILD, GuildRoles {
+            owner: OWNER,
+            names,
+            admins: [ADMIN].into_iter().collect(),
+        });
+
+        let mut rule = Rule::default();
+        rule.deny.insert(MUTED);
+        let mut settings = PermissionSettings {
+            dj_role: Some(DJ),
+            ..Default::default()
+        };
+        settings.rules.insert("enqueue".to_string(), rule);
+
+        let permissions = Permissions {
+            client: Client::new(String::new()).pipe(Arc::new),
+            roles,
+        };
+
+        (permissions, settings)
+    }
+
+    #[test]
+    fn check_roles() {
+        let (p, s) = permissions();
+        let check = |user, roles: &[u64], command| p.check(GUILD, user, roles, &s, command);
+
+        assert_eq!(check(OWNER, &[], "permission"), Ok(Access::Privileged));
+        assert_eq!(check(2, &[ADMIN, MUTED], "stop"), Ok(Access::Privileged));
+        assert!(check(2, &[DJ], "permission").is_err());
+        assert!(check(2, &[DJ], "config").is_err());
+        assert!(check(2, &[DJ], "alias").is_err());
+
+        assert_eq!(check(2, &[], "enqueue"), Ok(Access::All));
+        assert!(check(2, &[MUTED], "enqueue").is_err());
+
+        assert_eq!(check(2, &[DJ], "stop"), Ok(Access::Privileged));
+        assert!(check(2, &[], "stop").is_err());
+        assert_eq!(check(2, &[], "drop"), Ok(Access::OwnTracks));
+        assert_eq!(check(2, &[], "skip"), Ok(Access::OwnTracks));
+        assert!(check(2, &[], "previous").is_err());
+        assert_eq!(check(2, &[], "replay"), Ok(Access::All));
+        assert_eq!(check(2, &[], "show-queue"), Ok(Access::All));
+    }
+}
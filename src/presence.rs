@@ -0,0 +1,294 @@
+use alloc::sync::Arc;
+use core::fmt::Display;
+use core::str::FromStr;
+use core::time::Duration;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use actix::prelude::{
+    Actor, ActorFutureExt, ArbiterService, AsyncContext, Context, ContextFutureSpawner, Handler,
+    Message, ResponseActFuture, Supervised, WrapFuture,
+};
+use serde::{Deserialize, Serialize};
+use songbird::id::GuildId;
+use songbird::tracks::PlayMode;
+use songbird::Songbird;
+
+use crate::connection::{Connector, Disconnect};
+use crate::settings::{GetGuildSettings, Settings};
+use crate::timespec::{self, TimeSpec};
+use crate::util::Pipe;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// `Off` disables the policy, saved as "off" or "(secs)s"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Timeout {
+    Off,
+    After(Duration),
+}
+impl Timeout {
+    fn is_over(self, since: Option<Instant>) -> bool {
+        match (self, since) {
+            (Timeout::After(d), Some(since)) => since.elapsed() >= d,
+            _ => false,
+        }
+    }
+}
+impl FromStr for Timeout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Timeout::Off),
+            _ => match timespec::parse(s)? {
+                TimeSpec::Absolute(d) => Ok(Timeout::After(d)),
+                _ => "must not be relative".to_string().pipe(Err),
+            },
+        }
+    }
+}
+impl TryFrom<String> for Timeout {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> { s.parse() }
+}
+impl From<Timeout> for String {
+    fn from(t: Timeout) -> Self { t.to_string() }
+}
+impl Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Timeout::Off => write!(f, "off"),
+            Timeout::After(d) => write!(f, "{}s", d.as_secs()),
+        }
+    }
+}
+
+/// saved with settings of the guild
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+    /// leaves after the last listener left
+    pub leave_empty: Timeout,
+    /// leaves after the queue became empty
+    pub leave_idle: Timeout,
+    /// pauses while nobody is listening
+    pub pause_empty: bool,
+}
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            leave_empty: Timeout::After(Duration::from_secs(5 * 60)),
+            leave_idle: Timeout::After(Duration::from_secs(15 * 60)),
+            pause_empty: true,
+        }
+    }
+}
+
+#[derive(Default)]
+struct GuildState {
+    empty_since: Option<Instant>,
+    idle_since: Option<Instant>,
+    auto_paused: bool,
+}
+
+/// applies policies per guild, driven by voice states
+///
+/// policies are owned by `Settings`
+pub struct Presence {
+    songbird: Arc<Songbird>,
+    // only guilds where connected
+    states: HashMap<u64, GuildState>,
+}
+impl Presence {
+    pub fn new(songbird: Arc<Songbird>) -> Self {
+        Self {
+            songbird,
+            states: HashMap::new(),
+        }
+    }
+
+    async fn policy(guild: u64) -> Policy {
+        Settings::from_registry()
+            .send(GetGuildSettings { guild })
+            .await
+            .expect("failed sending")
+            .policy
+    }
+
+    fn check(&mut self, ctx: &mut Context<Self>) {
+        let songbird = self.songbird.clone();
+        let guilds: Vec<_> = self.states.keys().copied().collect();
+
+        async move {
+            let mut idles = vec![];
+            for guild in guilds {
+                let idle: Option<bool> = try {
+                    let call = songbird.get(GuildId::from(guild))?;
+                    let call = call.lock().await;
+                    call.current_channel()?;
+                    call.queue().is_empty()
+                };
+                idles.push((guild, idle, Self::policy(guild).await));
+            }
+            idles
+        }
+        .into_actor(self)
+        .map(|idles, this, ctx| {
+            for (guild, idle, policy) in idles {
+                let idle = match idle {
+                    Some(i) => i,
+                    None => {
+                        this.states.remove(&guild);
+                        continue;
+                    },
+                };
+
+                let state = this.states.entry(guild).or_default();
+                match idle {
+                    true => {
+                        state.idle_since.get_or_insert_with(Instant::now);
+                    },
+                    false => state.idle_since = None,
+                }
+
+                let reason = if policy.leave_empty.is_over(state.empty_since) {
+                    "nobody is listening"
+                } else if policy.leave_idle.is_over(state.idle_since) {
+                    "queue is empty"
+                } else {
+                    continue;
+                };
+                this.states.remove(&guild);
+
+                async move {
+                    let result = Connector::from_registry()
+                        .send(Disconnect { guild })
+                        .await
+                        .expect("failed sending");
+
+                    match result {
+                        Ok(_) => tracing::info!("left {} since {}", guild, reason),
+                        Err(e) => tracing::warn!("failed leaving {}: {}", guild, e),
+                    }
+                }
+                .into_actor(this)
+                .spawn(ctx);
+            }
+        })
+        .spawn(ctx);
+    }
+}
+impl Default for Presence {
+    fn default() -> Self { panic!("cannot initialize on Default::default") }
+}
+impl Actor for Presence {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(CHECK_INTERVAL, |this, ctx| this.check(ctx));
+    }
+}
+impl Handler<ListenersChanged> for Presence {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(
+        &mut self,
+        ListenersChanged {
+            guild,
+            channel,
+            listeners,
+        }: ListenersChanged,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let songbird = self.songbird.clone();
+        let resume = listeners > 0 && self.states.get(&guild).is_some_and(|s| s.auto_paused);
+
+        async move {
+            let pause = listeners == 0 && Self::policy(guild).await.pause_empty;
+
+            let call = songbird.get(GuildId::from(guild))?;
+            let call = call.lock().await;
+            if call.current_channel()?.0 != channel {
+                return None;
+            }
+
+            let queue = call.queue();
+            let paused = match queue.current() {
+                Some(handle) if pause => {
+                    let playing =
+                        matches!(handle.get_info().await, Ok(s) if s.playing == PlayMode::Play);
+                    playing && handle.pause().is_ok()
+                },
+                Some(handle) if resume => {
+                    if let Err(e) = handle.play() {
+                        tracing::warn!("failed resuming {}: {}", guild, e);
+                    }
+                    false
+                },
+                _ => false,
+            };
+
+            Some(paused)
+        }
+        .into_actor(self)
+        .map(move |paused, this, _| {
+            let paused = match paused {
+                Some(p) => p,
+                None => return,
+            };
+
+            let state = this.states.entry(guild).or_default();
+            match listeners {
+                0 => {
+                    state.empty_since.get_or_insert_with(Instant::now);
+                    state.auto_paused |= paused;
+                },
+                _ => {
+                    state.empty_since = None;
+                    state.auto_paused = false;
+                },
+            }
+        })
+        .pipe(Box::pin)
+    }
+}
+impl Supervised for Presence {}
+impl ArbiterService for Presence {}
+
+/// number of listeners (excluding bots) in the channel changed
+pub struct ListenersChanged {
+    pub guild: u64,
+    pub channel: u64,
+    pub listeners: usize,
+}
+impl Message for ListenersChanged {
+    type Result = ();
+}
+
+/// `None` keeps the current value, and returns updated policy (handled by
+/// `Settings`)
+pub struct UpdatePolicy {
+    pub guild: u64,
+    pub leave_empty: Option<Timeout>,
+    pub leave_idle: Option<Timeout>,
+    pub pause_empty: Option<bool>,
+}
+impl Message for UpdatePolicy {
+    type Result = Result<Policy, String>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_timeout() {
+        assert_eq!("off".parse(), Ok(Timeout::Off));
+        assert_eq!("5m".parse(), Ok(Timeout::After(Duration::from_secs(300))));
+        assert!("+5m".parse::<Timeout>().is_err());
+        assert!("soon".parse::<Timeout>().is_err());
+    }
+}
@@ -0,0 +1,552 @@
+use core::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use actix::prelude::{
+    Actor, ActorFutureExt, ArbiterService, Context, Handler, Message, ResponseActFuture,
+    Supervised, WrapFuture,
+};
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::gateway::Attachment;
+use crate::util::{repository_dir, Pipe};
+
+const UPLOAD_LIMIT: u64 = 32 * 1024 * 1024;
+
+/// stores uploaded files as "{dir}/{id}.{ext}" and its index as
+/// "{dir}/index.json"
+pub struct Repository {
+    dir: PathBuf,
+    index: Index,
+}
+impl Default for Repository {
+    fn default() -> Self {
+        let dir = PathBuf::from(repository_dir());
+        if let Err(e) = fs::create_dir_all(&dir) {
+            tracing::error!("failed creating repository: {}", e);
+        }
+
+        let index = match fs::read(dir.join("index.json")) {
+            Ok(mut bytes) => simd_json::from_slice(&mut bytes).unwrap_or_else(|e| {
+                tracing::error!("failed loading repository index: {}", e);
+                Index::default()
+            }),
+            Err(_) => Index::default(),
+        };
+
+        Self { dir, index }
+    }
+}
+impl Repository {
+    fn save(&self) -> Result<(), String> {
+        let bytes = simd_json::to_vec(&self.index).map_err(|e| e.to_string())?;
+
+        let path = self.dir.join("index.json");
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, bytes).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, &path).map_err(|e| e.to_string())
+    }
+
+    fn try_get_accessible(
+        &self,
+        id: u64,
+        user: u64,
+        guild: Option<u64>,
+    ) -> Result<&StoredTrack, String> {
+        let track = match self.index.tracks.iter().find(|t| t.id == id) {
+            Some(t) => t,
+            None => return format!("no such track: #{}", id).pipe(Err),
+        };
+
+        if !track.is_accessible(user, guild) {
+            return "you have no access to the track".to_string().pipe(Err);
+        }
+
+        Ok(track)
+    }
+
+    fn try_get_owned(&mut self, id: u64, user: u64) -> Result<&mut StoredTrack, String> {
+        let track = match self.index.tracks.iter_mut().find(|t| t.id == id) {
+            Some(t) => t,
+            None => return format!("no such track: #{}", id).pipe(Err),
+        };
+
+        if track.owner != user {
+            return "you are not the owner of the track".to_string().pipe(Err);
+        }
+
+        Ok(track)
+    }
+
+    /// downloads into "{id}.part" first, and aborts once exceeding the limit
+    async fn download(url: String, path: &Path) -> Result<(), String> {
+        let part = path.with_extension("part");
+
+        let result: Result<_, String> = try {
+            let mut response = reqwest::get(url)
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.to_string())?;
+
+            let mut file = tokio::fs::File::create(&part)
+                .await
+                .map_err(|e| e.to_string())?;
+            let mut size = 0;
+            while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+                size += chunk.len() as u64;
+                if size > UPLOAD_LIMIT {
+                    Err("too large file".to_string())?;
+                }
+                file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+            }
+            file.flush().await.map_err(|e| e.to_string())?;
+            drop(file);
+
+            tokio::fs::rename(&part, path)
+                .await
+                .map_err(|e| e.to_string())?
+        };
+
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&part).await;
+        }
+
+        result
+    }
+}
+impl Actor for Repository {
+    type Context = Context<Self>;
+}
+impl Handler<Upload> for Repository {
+    type Result = ResponseActFuture<Self, Result<Vec<StoredTrack>, String>>;
+
+    fn handle(
+        &mut self,
+        Upload {
+            owner,
+            guild,
+            attachments,
+            name,
+        }: Upload,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let first_id = self.index.next_id;
+        let result: Result<_, String> = try {
+            if attachments.is_empty() {
+                Err("no attachments".to_string())?;
+            }
+            if name.is_some() && attachments.len() > 1 {
+                Err("cannot name multiple attachments at once".to_string())?;
+            }
+
+            let mut pending = vec![];
+            for Attachment {
+                filename,
+                url,
+                size,
+                content_type,
+            } in attachments
+            {
+                if size > UPLOAD_LIMIT {
+                    Err(format!("too large file: {}", filename))?;
+                }
+                if let Some(ct) = content_type {
+                    if !ct.starts_with("audio/") && !ct.starts_with("video/") {
+                        Err(format!("not an audio file: {}", filename))?;
+                    }
+                }
+
+                let id = self.index.next_id;
+                self.index.next_id += 1;
+
+                let ext = PathBuf::from(&filename)
+                    .extension()
+                    .map(|e| e.to_string_lossy().into_owned())
+                    .filter(|e| e.chars().all(|c| c.is_ascii_alphanumeric()))
+                    .unwrap_or_else(|| "bin".to_string());
+                let track = StoredTrack {
+                    id,
+                    name: name.clone().unwrap_or(filename),
+                    owner,
+                    guild,
+                    file: format!("{}.{}", id, ext),
+                    uploaded_at: SystemTime::now(),
+                    visibility: Visibility::Private,
+                    shared_users: vec![],
+                    shared_guilds: vec![],
+                };
+
+                pending.push((url, self.dir.join(&track.file), track));
+            }
+            (pending, first_id..self.index.next_id)
+        };
+
+        let (pending, ids) = match result {
+            Ok(o) => o,
+            Err(e) => {
+                self.index.next_id = first_id;
+                return async move { Err(e) }.into_actor(self).pipe(Box::pin);
+            },
+        };
+
+        async move {
+            let mut stored = vec![];
+            for (url, path, track) in pending {
+                if let Err(e) = Self::download(url, &path).await {
+                    // all or nothing, not to leave files out of the index
+                    for (path, _) in stored {
+                        let _ = tokio::fs::remove_file(path).await;
+                    }
+                    return format!("failed uploading {}: {}", track.name, e).pipe(Err);
+                }
+                stored.push((path, track));
+            }
+            stored
+                .into_iter()
+                .map(|(_, t)| t)
+                .collect::<Vec<_>>()
+                .pipe(Ok)
+        }
+        .into_actor(self)
+        .map(move |result: Result<Vec<StoredTrack>, String>, this, _| {
+            let stored = match result {
+                Ok(s) => s,
+                Err(e) => {
+                    // gives back the ids, unless other uploads reserved later ones
+                    if this.index.next_id == ids.end {
+                        this.index.next_id = ids.start;
+                    }
+                    return Err(e);
+                },
+            };
+            this.index.tracks.extend(stored.iter().cloned());
+            this.save()?;
+
+            Ok(stored)
+        })
+        .pipe(Box::pin)
+    }
+}
+impl Handler<ListTracks> for Repository {
+    type Result = Vec<StoredTrack>;
+
+    fn handle(
+        &mut self,
+        ListTracks { user, guild }: ListTracks,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        self.index
+            .tracks
+            .iter()
+            .filter(|t| t.is_accessible(user, guild))
+            .cloned()
+            .collect()
+    }
+}
+impl Handler<ResolveTrack> for Repository {
+    type Result = Result<(PathBuf, StoredTrack), String>;
+
+    fn handle(
+        &mut self,
+        ResolveTrack { id, user, guild }: ResolveTrack,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let track = self.try_get_accessible(id, user, guild)?;
+
+        (self.dir.join(&track.file), track.clone()).pipe(Ok)
+    }
+}
+impl Handler<RenameTrack> for Repository {
+    type Result = Result<String, String>;
+
+    fn handle(
+        &mut self,
+        RenameTrack { id, user, name }: RenameTrack,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        self.try_get_owned(id, user)?.name = name;
+        self.save()?;
+
+        "renamed".to_string().pipe(Ok)
+    }
+}
+impl Handler<DeleteTrack> for Repository {
+    type Result = Result<String, String>;
+
+    fn handle(
+        &mut self,
+        DeleteTrack { id, user }: DeleteTrack,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let file = self.try_get_owned(id, user)?.file.clone();
+
+        self.index.tracks.retain(|t| t.id != id);
+        self.save()?;
+
+        if let Err(e) = fs::remove_file(self.dir.join(file)) {
+            tracing::warn!("failed removing file of #{}: {}", id, e);
+        }
+
+        "deleted".to_string().pipe(Ok)
+    }
+}
+impl Handler<SetVisibility> for Repository {
+    type Result = Result<String, String>;
+
+    fn handle(
+        &mut self,
+        SetVisibility {
+            id,
+            user,
+            visibility,
+        }: SetVisibility,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let track = self.try_get_owned(id, user)?;
+        if visibility == Visibility::Guild && track.guild.is_none() {
+            return "the track was not uploaded in a guild"
+                .to_string()
+                .pipe(Err);
+        }
+
+        track.visibility = visibility;
+        self.save()?;
+
+        "changed visibility".to_string().pipe(Ok)
+    }
+}
+impl Handler<ShareTrack> for Repository {
+    type Result = Result<String, String>;
+
+    fn handle(
+        &mut self,
+        ShareTrack {
+            id,
+            user,
+            target,
+            share,
+        }: ShareTrack,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let track = self.try_get_owned(id, user)?;
+
+        let (list, target) = match target {
+            ShareTarget::User(u) => (&mut track.shared_users, u),
+            ShareTarget::Guild(g) => (&mut track.shared_guilds, g),
+        };
+        let msg = match (share, list.contains(&target)) {
+            (true, true) => return "already shared".to_string().pipe(Err),
+            (false, false) => return "not shared".to_string().pipe(Err),
+            (true, false) => {
+                list.push(target);
+                "shared"
+            },
+            (false, true) => {
+                list.retain(|t| *t != target);
+                "unshared"
+            },
+        };
+        self.save()?;
+
+        msg.to_string().pipe(Ok)
+    }
+}
+impl Supervised for Repository {}
+impl ArbiterService for Repository {}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Index {
+    next_id: u64,
+    tracks: Vec<StoredTrack>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StoredTrack {
+    pub id: u64,
+    pub name: String,
+    pub owner: u64,
+    /// the guild where the track was uploaded
+    #[serde(default)]
+    pub guild: Option<u64>,
+    pub file: String,
+    pub uploaded_at: SystemTime,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub shared_users: Vec<u64>,
+    #[serde(default)]
+    pub shared_guilds: Vec<u64>,
+}
+impl StoredTrack {
+    fn is_accessible(&self, user: u64, guild: Option<u64>) -> bool {
+        let in_guild = |g| guild == Some(g);
+
+        self.owner == user
+            || self.visibility == Visibility::Public
+            || (self.visibility == Visibility::Guild && self.guild.into_iter().any(in_guild))
+            || self.shared_users.contains(&user)
+            || self.shared_guilds.iter().copied().any(in_guild)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ArgEnum)]
+pub enum Visibility {
+    /// only the owner and shared users / guilds
+    #[default]
+    Private,
+    /// anyone in the guild where the track was uploaded
+    Guild,
+    /// anyone
+    Public,
+}
+impl Display for Visibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Visibility::*;
+        let s = match self {
+            Private => "private",
+            Guild => "guild",
+            Public => "public",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+pub enum ShareTarget {
+    User(u64),
+    Guild(u64),
+}
+
+pub struct Upload {
+    pub owner: u64,
+    pub guild: Option<u64>,
+    pub attachments: Vec<Attachment>,
+    pub name: Option<String>,
+}
+impl Message for Upload {
+    type Result = Result<Vec<StoredTrack>, String>;
+}
+
+pub struct ListTracks {
+    pub user: u64,
+    pub guild: Option<u64>,
+}
+impl Message for ListTracks {
+    type Result = Vec<StoredTrack>;
+}
+
+pub struct ResolveTrack {
+    pub id: u64,
+    pub user: u64,
+    pub guild: Option<u64>,
+}
+impl Message for ResolveTrack {
+    type Result = Result<(PathBuf, StoredTrack), String>;
+}
+
+pub struct RenameTrack {
+    pub id: u64,
+    pub user: u64,
+    pub name: String,
+}
+impl Message for RenameTrack {
+    type Result = Result<String, String>;
+}
+
+pub struct DeleteTrack {
+    pub id: u64,
+    pub user: u64,
+}
+impl Message for DeleteTrack {
+    type Result = Result<String, String>;
+}
+
+pub struct SetVisibility {
+    pub id: u64,
+    pub user: u64,
+    pub visibility: Visibility,
+}
+impl Message for SetVisibility {
+    type Result = Result<String, String>;
+}
+
+pub struct ShareTrack {
+    pub id: u64,
+    pub user: u64,
+    pub target: ShareTarget,
+    pub share: bool,
+}
+impl Message for ShareTrack {
+    type Result = Result<String, String>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNER: u64 = 1;
+    const SHARED_USER: u64 = 2;
+    const OTHER: u64 = 3;
+    const UPLOADED_IN: u64 = 10;
+    const SHARED_GUILD: u64 = 20;
+    const OTHER_GUILD: u64 = 30;
+
+    fn track(visibility: Visibility) -> StoredTrack {
+        StoredTrack {
+            id: 0,
+            name: "track".to_string(),
+            owner: OWNER,
+            guild: Some(UPLOADED_IN),
+            file: "0.mp3".to_string(),
+            uploaded_at: SystemTime::UNIX_EPOCH,
+            visibility,
+            shared_users: vec![SHARED_USER],
+            shared_guilds: vec![SHARED_GUILD],
+        }
+    }
+
+    #[test]
+    fn accessibility() {
+        use Visibility::*;
+
+        // (visibility, user, guild, accessible)
+        let table = [
+            (Private, OWNER, None, true),
+            (Private, OWNER, Some(OTHER_GUILD), true),
+            (Private, SHARED_USER, None, true),
+            (Private, SHARED_USER, Some(OTHER_GUILD), true),
+            (Private, OTHER, Some(SHARED_GUILD), true),
+            (Private, OTHER, Some(UPLOADED_IN), false),
+            (Private, OTHER, Some(OTHER_GUILD), false),
+            (Private, OTHER, None, false),
+            (Guild, OTHER, Some(UPLOADED_IN), true),
+            (Guild, OTHER, Some(SHARED_GUILD), true),
+            (Guild, OTHER, Some(OTHER_GUILD), false),
+            (Guild, OTHER, None, false),
+            (Public, OTHER, Some(OTHER_GUILD), true),
+            (Public, OTHER, None, true),
+        ];
+
+        for (visibility, user, guild, accessible) in table {
+            assert_eq!(
+                track(visibility).is_accessible(user, guild),
+                accessible,
+                "{} by {} in {:?}",
+                visibility,
+                user,
+                guild
+            );
+        }
+
+        // uploaded in direct messages, so shared with no guild
+        let dm = StoredTrack {
+            guild: None,
+            ..track(Guild)
+        };
+        assert!(!dm.is_accessible(OTHER, Some(UPLOADED_IN)));
+        assert!(!dm.is_accessible(OTHER, None));
+    }
+}
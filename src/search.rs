@@ -0,0 +1,234 @@
+use alloc::sync::Arc;
+use core::time::Duration;
+use std::collections::HashMap;
+
+use actix::prelude::{
+    Actor, ActorFutureExt, ArbiterService, Context, Handler, Message, ResponseActFuture,
+    Supervised, WrapFuture,
+};
+use async_trait::async_trait;
+
+use crate::config::config;
+use crate::source::{self, FlatEntry};
+use crate::util::Pipe;
+
+#[async_trait]
+pub trait SearchBackend: Send + Sync {
+    async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchResult>, String>;
+}
+
+/// searches youtube via `youtube-dl "ytsearchN:..."`
+pub struct YoutubeSearch;
+#[async_trait]
+impl SearchBackend for YoutubeSearch {
+    async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchResult>, String> {
+        source::ytdl_flat(&format!("ytsearch{}:{}", count, query))
+            .await?
+            .into_iter()
+            .map(SearchResult::from)
+            .collect::<Vec<_>>()
+            .pipe(Ok)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub duration: Option<Duration>,
+    pub uploader: Option<String>,
+}
+impl From<FlatEntry> for SearchResult {
+    fn from(entry: FlatEntry) -> Self {
+        Self {
+            url: entry.url(),
+            title: entry.title.unwrap_or_else(|| entry.id.clone()),
+            duration: entry
+                .duration
+                .filter(|d| d.is_finite() && *d >= 0.0)
+                .map(Duration::from_secs_f64),
+            uploader: entry.uploader,
+        }
+    }
+}
+
+/// keeps the last results per (guild, user) to be picked later
+pub struct Searcher {
+    backend: Arc<dyn SearchBackend>,
+    results: HashMap<(u64, u64), Vec<SearchResult>>,
+}
+impl Searcher {
+    pub fn new(backend: Arc<dyn SearchBackend>) -> Self {
+        Self {
+            backend,
+            results: HashMap::new(),
+        }
+    }
+}
+impl Default for Searcher {
+    fn default() -> Self { panic!("cannot initialize on Default::default") }
+}
+impl Actor for Searcher {
+    type Context = Context<Self>;
+}
+impl Handler<Search> for Searcher {
+    type Result = ResponseActFuture<Self, Result<Vec<SearchResult>, String>>;
+
+    fn handle(
+        &mut self,
+        Search {
+            guild,
+            user,
+            query,
+            count,
+        }: Search,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let backend = self.backend.clone();
+
+        async move {
+            if query.trim().is_empty() {
+                return "empty query".to_string().pipe(Err);
+            }
+            let limit = config().limits.search;
+            if count == 0 || count > limit {
+                return format!("count must be in 1..={}", limit).pipe(Err);
+            }
+
+            backend.search(&query, count).await
+        }
+        .into_actor(self)
+        .map(move |result, this, _| {
+            let results = result?;
+            if results.is_empty() {
+                return "no results".to_string().pipe(Err);
+            }
+
+            this.results.insert((guild, user), results.clone());
+
+            Ok(results)
+        })
+        .pipe(Box::pin)
+    }
+}
+impl Handler<Pick> for Searcher {
+    type Result = Result<SearchResult, String>;
+
+    fn handle(&mut self, Pick { guild, user, index }: Pick, _: &mut Self::Context) -> Self::Result {
+        let results = match self.results.get(&(guild, user)) {
+            Some(r) => r,
+            None => return "search first".to_string().pipe(Err),
+        };
+
+        match index.checked_sub(1).and_then(|i| results.get(i)) {
+            Some(r) => r.clone().pipe(Ok),
+            None => "out of bounds".to_string().pipe(Err),
+        }
+    }
+}
+impl Supervised for Searcher {}
+impl ArbiterService for Searcher {}
+
+pub struct Search {
+    pub guild: u64,
+    pub user: u64,
+    pub query: String,
+    pub count: usize,
+}
+impl Message for Search {
+    type Result = Result<Vec<SearchResult>, String>;
+}
+
+/// `index` is 1-based, as listed
+pub struct Pick {
+    pub guild: u64,
+    pub user: u64,
+    pub index: usize,
+}
+impl Message for Pick {
+    type Result = Result<SearchResult, String>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StubBackend;
+    #[async_trait]
+    impl SearchBackend for StubBackend {
+        async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchResult>, String> {
+            (1..=count)
+                .map(|n| SearchResult {
+                    title: format!("{} #{}", query, n),
+                    url: format!("https://example.com/{}", n),
+                    duration: None,
+                    uploader: None,
+                })
+                .collect::<Vec<_>>()
+                .pipe(Ok)
+        }
+    }
+
+    #[actix::test]
+    async fn pick_returns_searched_result() {
+        let searcher = Searcher::new(Arc::new(StubBackend)).start();
+
+        let picked = searcher.send(Pick {
+            guild: 1,
+            user: 2,
+            index: 1,
+        });
+        assert!(picked.await.unwrap().is_err());
+
+        let results = searcher
+            .send(Search {
+                guild: 1,
+                user: 2,
+                query: "song".to_string(),
+                count: 3,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(results.len(), 3);
+
+        let picked = searcher.send(Pick {
+            guild: 1,
+            user: 2,
+            index: 2,
+        });
+        assert_eq!(picked.await.unwrap(), Ok(results[1].clone()));
+
+        for index in [0, 4] {
+            let picked = searcher.send(Pick {
+                guild: 1,
+                user: 2,
+                index,
+            });
+            assert!(picked.await.unwrap().is_err());
+        }
+
+        // results are kept per user
+        let picked = searcher.send(Pick {
+            guild: 1,
+            user: 3,
+            index: 1,
+        });
+        assert!(picked.await.unwrap().is_err());
+    }
+
+    #[actix::test]
+    async fn search_rejects_invalid_count() {
+        let searcher = Searcher::new(Arc::new(StubBackend)).start();
+
+        for count in [0, config().limits.search + 1] {
+            let result = searcher.send(Search {
+                guild: 1,
+                user: 2,
+                query: "song".to_string(),
+                count,
+            });
+            assert!(result.await.unwrap().is_err());
+        }
+    }
+}
@@ -0,0 +1,541 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use actix::prelude::{Actor, ArbiterService, Context, Handler, Message, Supervised};
+use actix::MessageResponse;
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::announce::{AnnounceSettings, UpdateAnnounceSettings};
+use crate::config::config;
+use crate::permission::{PermissionSettings, PermissionUpdate};
+use crate::presence::{Policy, UpdatePolicy};
+use crate::util::{data_dir, Pipe};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum SettingKey {
+    /// command prefixes, separated by spaces
+    Prefixes,
+    /// volume on joining, same as the argument of "volume"
+    DefaultVolume,
+    /// items per page of "show-queue" and "show-history"
+    PageSize,
+    /// channel where commands can be run without prefixes
+    MusicChannel,
+    /// votes to skip, as a percentage of listeners
+    VoteSkip,
+}
+impl SettingKey {
+    pub fn name(self) -> &'static str {
+        self.to_possible_value()
+            .expect("no skipped keys")
+            .get_name()
+    }
+}
+
+/// overrides of the config per guild, `None` falls back to it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, MessageResponse)]
+pub struct GuildSettings {
+    pub prefixes: Option<Vec<String>>,
+    pub default_volume: Option<f32>,
+    pub page_size: Option<usize>,
+    pub music_channel: Option<u64>,
+    /// percentage of listeners, `None` disables voting
+    pub vote_skip: Option<u8>,
+    /// name -> words replacing it
+    #[serde(default)]
+    pub aliases: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub permissions: PermissionSettings,
+    #[serde(default)]
+    pub policy: Policy,
+    #[serde(default)]
+    pub announce: AnnounceSettings,
+}
+impl GuildSettings {
+    pub fn prefixes(&self) -> Vec<String> {
+        match &self.prefixes {
+            Some(p) => p.clone(),
+            None => vec![config().prefix.clone()],
+        }
+    }
+
+    pub fn default_volume(&self) -> f32 { self.default_volume.unwrap_or(config().default_volume) }
+
+    pub fn page_size(&self) -> usize { self.page_size.unwrap_or(config().page_size) }
+
+    /// formatted value, and whether overridden
+    pub fn get(&self, key: SettingKey) -> (String, bool) {
+        use SettingKey::*;
+        match key {
+            Prefixes => (self.prefixes().join(" "), self.prefixes.is_some()),
+            DefaultVolume => (
+                self.default_volume().to_string(),
+                self.default_volume.is_some(),
+            ),
+            PageSize => (self.page_size().to_string(), self.page_size.is_some()),
+            MusicChannel => match self.music_channel {
+                Some(c) => (format!("<#{}>", c), true),
+                None => ("off".to_string(), false),
+            },
+            VoteSkip => match self.vote_skip {
+                Some(p) => (format!("{}%", p), true),
+                None => ("off".to_string(), false),
+            },
+        }
+    }
+
+    fn set(&mut self, key: SettingKey, value: &str) -> Result<(), String> {
+        use SettingKey::*;
+        match key {
+            Prefixes => {
+                let prefixes: Vec<_> = value.split_whitespace().map(String::from).collect();
+                if prefixes.is_empty() {
+                    return "specify one prefix at least".to_string().pipe(Err);
+                }
+                self.prefixes = Some(prefixes);
+            },
+            DefaultVolume => {
+                let volume: f32 = value
+                    .parse()
+                    .map_err(|_| format!("not a number: {}", value))?;
+                if !volume.is_finite() || volume < 0.0 {
+                    return "default volume must not be negative".to_string().pipe(Err);
+                }
+                self.default_volume = Some(volume);
+            },
+            PageSize => {
+                let limit = config().limits.max_page_size;
+                let size: usize = value
+                    .parse()
+                    .map_err(|_| format!("not a number: {}", value))?;
+                if !(1..=limit).contains(&size) {
+                    return format!("page size must be in 1..={}", limit).pipe(Err);
+                }
+                self.page_size = Some(size);
+            },
+            MusicChannel => {
+                let id = value
+                    .strip_prefix("<#")
+                    .and_then(|v| v.strip_suffix('>'))
+                    .unwrap_or(value);
+                let channel = id
+                    .parse()
+                    .map_err(|_| format!("not a channel: {}", value))?;
+                self.music_channel = Some(channel);
+            },
+            VoteSkip => {
+                if value == "off" {
+                    self.vote_skip = None;
+                    return Ok(());
+                }
+
+                let percent: u8 = value
+                    .strip_suffix('%')
+                    .unwrap_or(value)
+                    .parse()
+                    .map_err(|_| format!("not a percentage: {}", value))?;
+                if !(1..=100).contains(&percent) {
+                    return "percentage must be in 1..=100".to_string().pipe(Err);
+                }
+                self.vote_skip = Some(percent);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// resets every key, keeping aliases, permissions, policy and
+    /// announcements
+    fn reset_all(&mut self) {
+        SettingKey::value_variants()
+            .iter()
+            .for_each(|k| self.reset(*k));
+    }
+
+    fn reset(&mut self, key: SettingKey) {
+        use SettingKey::*;
+        match key {
+            Prefixes => self.prefixes = None,
+            DefaultVolume => self.default_volume = None,
+            PageSize => self.page_size = None,
+            MusicChannel => self.music_channel = None,
+            VoteSkip => self.vote_skip = None,
+        }
+    }
+}
+
+/// stores settings as "{dir}/settings/{guild}.json"
+pub struct Settings {
+    dir: PathBuf,
+    guilds: HashMap<u64, GuildSettings>,
+}
+impl Settings {
+    fn get(&self, guild: u64) -> GuildSettings {
+        self.guilds.get(&guild).cloned().unwrap_or_default()
+    }
+
+    fn load(&mut self) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+
+        for entry in fs::read_dir(&self.dir).map_err(|e| e.to_string())? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension() != Some(OsStr::new("json")) {
+                continue;
+            }
+
+            let result: Result<_, String> = try {
+                let guild = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| "not named by a guild".to_string())?;
+                let mut bytes = fs::read(&path).map_err(|e| e.to_string())?;
+                let settings = simd_json::from_slice(&mut bytes).map_err(|e| e.to_string())?;
+                (guild, settings)
+            };
+
+            match result {
+                Ok((guild, settings)) => {
+                    self.guilds.insert(guild, settings);
+                },
+                Err(e) => tracing::warn!("failed loading {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// saves `settings` first, and keeps them only if saved
+    fn update(&mut self, guild: u64, settings: GuildSettings) -> Result<(), String> {
+        self.save(guild, &settings)
+            .map_err(|e| format!("failed saving: {}", e))?;
+        self.guilds.insert(guild, settings);
+
+        Ok(())
+    }
+
+    fn save(&self, guild: u64, settings: &GuildSettings) -> Result<(), String> {
+        let path = self.dir.join(format!("{}.json", guild));
+
+        if *settings == GuildSettings::default() {
+            return match fs::remove_file(&path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(e.to_string()),
+                _ => Ok(()),
+            };
+        }
+        let bytes = simd_json::to_vec(settings).map_err(|e| e.to_string())?;
+
+        // write to temporary file first, not to break settings on failure
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, bytes).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, &path).map_err(|e| e.to_string())
+    }
+}
+impl Default for Settings {
+    fn default() -> Self {
+        let mut this = Self {
+            dir: PathBuf::from(data_dir()).join("settings"),
+            guilds: HashMap::new(),
+        };
+
+        if let Err(e) = this.load() {
+            tracing::error!("failed loading settings: {}", e);
+        }
+
+        this
+    }
+}
+impl Actor for Settings {
+    type Context = Context<Self>;
+}
+impl Handler<GetGuildSettings> for Settings {
+    type Result = GuildSettings;
+
+    fn handle(
+        &mut self,
+        GetGuildSettings { guild }: GetGuildSettings,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        self.get(guild)
+    }
+}
+impl Handler<SetSetting> for Settings {
+    type Result = Result<GuildSettings, String>;
+
+    fn handle(
+        &mut self,
+        SetSetting { guild, key, value }: SetSetting,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let mut settings = self.get(guild);
+        settings.set(key, &value)?;
+        self.update(guild, settings.clone())?;
+
+        Ok(settings)
+    }
+}
+impl Handler<ResetSettings> for Settings {
+    type Result = Result<GuildSettings, String>;
+
+    fn handle(
+        &mut self,
+        ResetSettings { guild, key }: ResetSettings,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let mut settings = self.get(guild);
+        match key {
+            Some(k) => settings.reset(k),
+            None => settings.reset_all(),
+        }
+        self.update(guild, settings.clone())?;
+
+        Ok(settings)
+    }
+}
+impl Handler<SetAlias> for Settings {
+    type Result = Result<GuildSettings, String>;
+
+    fn handle(
+        &mut self,
+        SetAlias {
+            guild,
+            name,
+            expansion,
+        }: SetAlias,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let mut settings = self.get(guild);
+        match expansion {
+            Some(e) => {
+                let limit = config().limits.aliases;
+                if !settings.aliases.contains_key(&name) && settings.aliases.len() >= limit {
+                    return format!("too many aliases (at most {})", limit).pipe(Err);
+                }
+                settings.aliases.insert(name, e);
+            },
+            None =>
+                if settings.aliases.remove(&name).is_none() {
+                    return format!("no such alias: {}", name).pipe(Err);
+                },
+        }
+        self.update(guild, settings.clone())?;
+
+        Ok(settings)
+    }
+}
+impl Handler<SetPermissions> for Settings {
+    type Result = Result<GuildSettings, String>;
+
+    fn handle(
+        &mut self,
+        SetPermissions { guild, update }: SetPermissions,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let mut settings = self.get(guild);
+        settings.permissions.apply(update);
+        self.update(guild, settings.clone())?;
+
+        Ok(settings)
+    }
+}
+impl Handler<UpdatePolicy> for Settings {
+    type Result = Result<Policy, String>;
+
+    fn handle(
+        &mut self,
+        UpdatePolicy {
+            guild,
+            leave_empty,
+            leave_idle,
+            pause_empty,
+        }: UpdatePolicy,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let mut settings = self.get(guild);
+        let policy = &mut settings.policy;
+
+        if let Some(t) = leave_empty {
+            policy.leave_empty = t;
+        }
+        if let Some(t) = leave_idle {
+            policy.leave_idle = t;
+        }
+        if let Some(b) = pause_empty {
+            policy.pause_empty = b;
+        }
+
+        let policy = *policy;
+        self.update(guild, settings)?;
+
+        Ok(policy)
+    }
+}
+impl Handler<UpdateAnnounceSettings> for Settings {
+    type Result = Result<AnnounceSettings, String>;
+
+    fn handle(
+        &mut self,
+        UpdateAnnounceSettings {
+            guild,
+            now_playing,
+            status,
+        }: UpdateAnnounceSettings,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let mut settings = self.get(guild);
+        let announce = &mut settings.announce;
+
+        if let Some(b) = now_playing {
+            announce.now_playing = b;
+        }
+        if let Some(b) = status {
+            announce.status = b;
+        }
+
+        let announce = *announce;
+        self.update(guild, settings)?;
+
+        Ok(announce)
+    }
+}
+impl Supervised for Settings {}
+impl ArbiterService for Settings {}
+
+pub struct GetGuildSettings {
+    pub guild: u64,
+}
+impl Message for GetGuildSettings {
+    type Result = GuildSettings;
+}
+
+/// validates and saves the value, and returns updated settings
+pub struct SetSetting {
+    pub guild: u64,
+    pub key: SettingKey,
+    pub value: String,
+}
+impl Message for SetSetting {
+    type Result = Result<GuildSettings, String>;
+}
+
+/// resets all if `key` is `None`, and returns updated settings
+pub struct ResetSettings {
+    pub guild: u64,
+    pub key: Option<SettingKey>,
+}
+impl Message for ResetSettings {
+    type Result = Result<GuildSettings, String>;
+}
+
+/// removes the alias if `expansion` is `None`, and returns updated settings
+pub struct SetAlias {
+    pub guild: u64,
+    pub name: String,
+    pub expansion: Option<Vec<String>>,
+}
+impl Message for SetAlias {
+    type Result = Result<GuildSettings, String>;
+}
+
+/// returns updated settings
+pub struct SetPermissions {
+    pub guild: u64,
+    pub update: PermissionUpdate,
+}
+impl Message for SetPermissions {
+    type Result = Result<GuildSettings, String>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_settings() {
+        let mut settings = GuildSettings::default();
+        assert_eq!(
+            settings.get(SettingKey::Prefixes),
+            ("*v".to_string(), false)
+        );
+
+        settings.set(SettingKey::Prefixes, "!v  ?v").unwrap();
+        assert_eq!(settings.prefixes(), ["!v", "?v"]);
+        assert!(settings.set(SettingKey::Prefixes, " ").is_err());
+
+        settings.set(SettingKey::MusicChannel, "<#42>").unwrap();
+        assert_eq!(settings.music_channel, Some(42));
+        assert!(settings.set(SettingKey::MusicChannel, "music").is_err());
+
+        settings.set(SettingKey::VoteSkip, "50%").unwrap();
+        assert_eq!(
+            settings.get(SettingKey::VoteSkip),
+            ("50%".to_string(), true)
+        );
+        assert!(settings.set(SettingKey::VoteSkip, "0").is_err());
+        settings.set(SettingKey::VoteSkip, "off").unwrap();
+        assert_eq!(settings.vote_skip, None);
+
+        settings.set(SettingKey::PageSize, "5").unwrap();
+        assert_eq!(settings.page_size(), 5);
+        assert!(settings.set(SettingKey::PageSize, "0").is_err());
+        assert!(settings.set(SettingKey::PageSize, "100").is_err());
+        assert!(settings.set(SettingKey::DefaultVolume, "-1").is_err());
+
+        settings.reset(SettingKey::PageSize);
+        assert_eq!(settings.page_size(), 10);
+    }
+
+    #[test]
+    fn reset_keeps_aliases() {
+        let mut settings = GuildSettings::default();
+        settings.set(SettingKey::Prefixes, "!v").unwrap();
+        settings.set(SettingKey::VoteSkip, "50").unwrap();
+        settings
+            .aliases
+            .insert("lofi".to_string(), vec!["enqueue".to_string()]);
+
+        settings.reset_all();
+        assert_eq!(settings.prefixes, None);
+        assert_eq!(settings.vote_skip, None);
+        assert_eq!(settings.aliases["lofi"], ["enqueue"]);
+    }
+
+    #[test]
+    fn keep_settings_unless_saved() {
+        // a file in place of the directory, so saving fails
+        let dir = std::env::temp_dir().join(format!("vivara-settings-{}", std::process::id()));
+        fs::write(&dir, b"").unwrap();
+        let mut settings = Settings {
+            dir: dir.clone(),
+            guilds: HashMap::new(),
+        };
+
+        let mut updated = GuildSettings::default();
+        updated.set(SettingKey::VoteSkip, "50").unwrap();
+        assert!(settings.update(1, updated).is_err());
+        assert_eq!(settings.get(1), GuildSettings::default());
+
+        fs::remove_file(&dir).unwrap();
+    }
+
+    #[test]
+    fn serialize_settings() {
+        let mut settings = GuildSettings::default();
+        settings.policy.pause_empty = false;
+        settings.announce.status = true;
+        settings.permissions.dj_role = Some(42);
+
+        let mut bytes = simd_json::to_vec(&settings).unwrap();
+        let loaded: GuildSettings = simd_json::from_slice(&mut bytes).unwrap();
+        assert_eq!(loaded, settings);
+
+        // saved before policy, announcements and permissions were added
+        let mut bytes = br#"{"prefixes":null,"default_volume":null,"page_size":null,"music_channel":null,"vote_skip":null}"#.to_vec();
+        let loaded: GuildSettings = simd_json::from_slice(&mut bytes).unwrap();
+        assert_eq!(loaded, GuildSettings::default());
+    }
+}
@@ -0,0 +1,264 @@
+use core::fmt::Display;
+use core::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+
+use actix::ArbiterService;
+use serde::{Deserialize, Serialize};
+use songbird::input::{Input, Metadata, Restartable};
+use tokio::process::Command;
+use url::Url;
+
+use crate::repository::{self, ResolveTrack};
+use crate::util::{media_dir, Pipe};
+
+/// tagged by "kind", as simd-json cannot deserialize externally tagged enums
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Source {
+    Youtube { url: String },
+    Local { path: String },
+    Repository { id: u64 },
+}
+impl Source {
+    /// `user` and `guild` are used for checking accessibility of repository
+    /// tracks
+    pub async fn input(&self, user: u64, guild: u64) -> Result<Input, String> {
+        use Source::*;
+        match self {
+            Youtube { url } => Restartable::ytdl(url.clone(), true).await,
+            Local { path } => {
+                let path = resolve_local(media_dir().as_ref(), path.as_ref())?;
+                Restartable::ffmpeg(path, true).await
+            },
+            Repository { id } => {
+                let (path, _) = repository::Repository::from_registry()
+                    .send(ResolveTrack {
+                        id: *id,
+                        user,
+                        guild: Some(guild),
+                    })
+                    .await
+                    .expect("failed sending")?;
+                Restartable::ffmpeg(path, true).await
+            },
+        }
+        .map_err(|e| e.to_string())?
+        .pipe(Input::from)
+        .pipe(Ok)
+    }
+
+    /// same as `input`, but keeps `metadata` of a previous input for youtube
+    /// tracks
+    pub async fn input_with(
+        &self,
+        user: u64,
+        guild: u64,
+        metadata: &Metadata,
+    ) -> Result<Input, String> {
+        let mut input = self.input(user, guild).await?;
+        if let Source::Youtube { .. } = self {
+            input.metadata = metadata.clone().pipe(Box::new);
+        }
+
+        Ok(input)
+    }
+}
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Source::*;
+        match self {
+            Youtube { url } => write!(f, "<{}>", url),
+            Local { path } => write!(f, "local:{}", path),
+            Repository { id } => write!(f, "repository:#{}", id),
+        }
+    }
+}
+
+/// whether `url` points a youtube playlist page ("/playlist?list=...")
+pub fn is_playlist(url: &Url) -> bool {
+    let host = match url.host_str() {
+        Some(h) => h.trim_start_matches("www.").trim_start_matches("music."),
+        None => return false,
+    };
+
+    host == "youtube.com"
+        && url.path() == "/playlist"
+        && url.query_pairs().any(|(k, _)| k == "list")
+}
+
+/// expands playlist into urls of its entries, without fetching each entry
+pub async fn expand_playlist(url: &str) -> Result<Vec<String>, String> {
+    ytdl_flat(url)
+        .await?
+        .iter()
+        .map(FlatEntry::url)
+        .collect::<Vec<_>>()
+        .pipe(Ok)
+}
+
+/// an entry listed by `youtube-dl --flat-playlist`
+#[derive(Deserialize)]
+pub struct FlatEntry {
+    pub id: String,
+    pub title: Option<String>,
+    pub duration: Option<f64>,
+    pub uploader: Option<String>,
+}
+impl FlatEntry {
+    pub fn url(&self) -> String { format!("https://www.youtube.com/watch?v={}", self.id) }
+}
+
+/// lists entries of `target` (a playlist url or a "ytsearchN:" query)
+pub async fn ytdl_flat(target: &str) -> Result<Vec<FlatEntry>, String> {
+    #[derive(Deserialize)]
+    struct Playlist {
+        entries: Vec<FlatEntry>,
+    }
+
+    let output = Command::new("youtube-dl")
+        .args(["--flat-playlist", "-J", target])
+        .output()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !output.status.success() {
+        return format!(
+            "youtube-dl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .pipe(Err);
+    }
+
+    let mut stdout = output.stdout;
+    let Playlist { entries } = simd_json::from_slice(&mut stdout).map_err(|e| e.to_string())?;
+
+    Ok(entries)
+}
+
+/// picks `items` (1-based, all if `None`) of `entries`, at most `limit`, and
+/// whether truncated by `limit`
+pub fn select_items<T>(
+    entries: Vec<T>,
+    items: Option<(Bound<usize>, Bound<usize>)>,
+    limit: usize,
+) -> (Vec<T>, bool) {
+    let items = items.unwrap_or((Bound::Unbounded, Bound::Unbounded));
+
+    let mut selected = entries
+        .into_iter()
+        .zip(1..)
+        .filter(|(_, n)| items.contains(n))
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+
+    let truncated = selected.len() > limit;
+    selected.truncate(limit);
+
+    (selected, truncated)
+}
+
+/// path of a "file://" url, relative to the media directory (e.g.
+/// "file:///album/track.mp3" is "album/track.mp3")
+pub fn local_path(url: &Url) -> Result<String, String> {
+    let path = url
+        .to_file_path()
+        .map_err(|()| "invalid file url".to_string())?;
+
+    path.strip_prefix("/")
+        .unwrap_or(&path)
+        .to_string_lossy()
+        .into_owned()
+        .pipe(Ok)
+}
+
+/// resolves `path` in `root`, rejecting anything outside of `root` (including
+/// via `..` or symlinks)
+///
+/// every rejection is the same error, not to tell which paths exist
+pub fn resolve_local(root: &Path, path: &Path) -> Result<PathBuf, String> {
+    let root = root
+        .canonicalize()
+        .map_err(|e| format!("media directory is unavailable: {}", e))?;
+
+    match root.join(path).canonicalize() {
+        Ok(resolved) if resolved.starts_with(&root) && resolved.is_file() => Ok(resolved),
+        _ => format!("no such file: {}", path.display()).pipe(Err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn resolve_local_rejects_traversal() {
+        let base = std::env::temp_dir().join(format!("vivara-source-{}", std::process::id()));
+        let root = base.join("media");
+        fs::create_dir_all(root.join("album")).unwrap();
+        fs::write(root.join("album/track.mp3"), b"").unwrap();
+        fs::write(base.join("secret.txt"), b"").unwrap();
+
+        assert!(resolve_local(&root, "album/track.mp3".as_ref()).is_ok());
+        assert!(resolve_local(&root, "album/../album/track.mp3".as_ref()).is_ok());
+        assert!(resolve_local(&root, &root.join("album/track.mp3")).is_ok());
+
+        assert!(resolve_local(&root, "../secret.txt".as_ref()).is_err());
+        assert!(resolve_local(&root, &base.join("secret.txt")).is_err());
+        assert!(resolve_local(&root, "album".as_ref()).is_err());
+        assert!(resolve_local(&root, "missing.mp3".as_ref()).is_err());
+
+        // existing or not, outside of root is the same error
+        let denied = |p: &str| {
+            resolve_local(&root, p.as_ref())
+                .unwrap_err()
+                .replace(p, "_")
+        };
+        assert_eq!(denied("../secret.txt"), denied("../missing.txt"));
+
+        let url = "file:///album/track.mp3".parse().unwrap();
+        let path = local_path(&url).unwrap();
+        assert_eq!(path, "album/track.mp3");
+        assert!(resolve_local(&root, path.as_ref()).is_ok());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn is_playlist_detects_playlist_pages() {
+        let check = |s: &str| is_playlist(&s.parse().unwrap());
+
+        assert!(check("https://www.youtube.com/playlist?list=PL0123"));
+        assert!(check("https://music.youtube.com/playlist?list=PL0123"));
+
+        assert!(!check("https://www.youtube.com/watch?v=abc&list=PL0123"));
+        assert!(!check("https://www.youtube.com/playlist"));
+        assert!(!check("https://example.com/playlist?list=PL0123"));
+    }
+
+    #[test]
+    fn select_items_applies_range_and_limit() {
+        use Bound::*;
+        let entries = (1..=10).collect::<Vec<_>>();
+
+        assert_eq!(
+            select_items(entries.clone(), None, 3),
+            (vec![1, 2, 3], true)
+        );
+        assert_eq!(
+            select_items(entries.clone(), Some((Included(3), Excluded(6))), 10),
+            (vec![3, 4, 5], false)
+        );
+        assert_eq!(
+            select_items(entries.clone(), Some((Included(8), Unbounded)), 10),
+            (vec![8, 9, 10], false)
+        );
+        assert_eq!(
+            select_items(entries.clone(), Some((Included(2), Included(9))), 2),
+            (vec![2, 3], true)
+        );
+        // exactly as many as the limit
+        assert!(!select_items(entries, None, 10).1);
+    }
+}
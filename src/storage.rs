@@ -0,0 +1,147 @@
+use core::time::Duration;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::connection::{TrackData, TrackInfo};
+
+pub trait Storage: Send + Sync {
+    fn load(&self) -> Result<Vec<GuildSnapshot>, String>;
+
+    fn save(&self, snapshot: &GuildSnapshot) -> Result<(), String>;
+
+    fn remove(&self, guild: u64) -> Result<(), String>;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GuildSnapshot {
+    pub guild: u64,
+    pub channel: Option<u64>,
+    pub default_volume: f32,
+    pub queue: Vec<QueuedTrack>,
+    pub history: Vec<TrackInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QueuedTrack {
+    pub data: TrackData,
+    pub position: Duration,
+}
+
+/// stores snapshots as "{dir}/{guild}.json"
+pub struct FileStorage {
+    dir: PathBuf,
+}
+impl FileStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, String> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+        Ok(Self { dir })
+    }
+
+    fn path(&self, guild: u64) -> PathBuf { self.dir.join(format!("{}.json", guild)) }
+}
+impl Storage for FileStorage {
+    fn load(&self) -> Result<Vec<GuildSnapshot>, String> {
+        let mut snapshots = vec![];
+
+        for entry in fs::read_dir(&self.dir).map_err(|e| e.to_string())? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension() != Some(OsStr::new("json")) {
+                continue;
+            }
+
+            let result: Result<_, String> = try {
+                let mut bytes = fs::read(&path).map_err(|e| e.to_string())?;
+                simd_json::from_slice(&mut bytes).map_err(|e| e.to_string())?
+            };
+
+            match result {
+                Ok(o) => snapshots.push(o),
+                Err(e) => tracing::warn!("failed loading {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(snapshots)
+    }
+
+    fn save(&self, snapshot: &GuildSnapshot) -> Result<(), String> {
+        let bytes = simd_json::to_vec(snapshot).map_err(|e| e.to_string())?;
+
+        // write to temporary file first, not to break snapshot on failure
+        let path = self.path(snapshot.guild);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, bytes).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, &path).map_err(|e| e.to_string())
+    }
+
+    fn remove(&self, guild: u64) -> Result<(), String> {
+        match fs::remove_file(self.path(guild)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::source::Source;
+
+    #[test]
+    fn file_storage_round_trip() {
+        let dir = std::env::temp_dir().join(format!("vivara-storage-{}", std::process::id()));
+        let storage = FileStorage::new(&dir).unwrap();
+
+        let data = TrackData {
+            source: Source::Local {
+                path: "track.mp3".to_string(),
+            },
+            user: 2,
+            title: Some("track".to_string()),
+            artist: None,
+            duration: Some(Duration::from_secs(180)),
+            thumbnail: None,
+            source_url: None,
+            channel: Some(3),
+        };
+        let snapshot = GuildSnapshot {
+            guild: 1,
+            channel: Some(4),
+            default_volume: 0.5,
+            queue: vec![QueuedTrack {
+                data: data.clone(),
+                position: Duration::from_secs(30),
+            }],
+            history: vec![TrackInfo {
+                data,
+                finished_at: SystemTime::UNIX_EPOCH,
+                position: Duration::from_secs(180),
+            }],
+        };
+        storage.save(&snapshot).unwrap();
+
+        let loaded = storage.load().unwrap();
+        assert_eq!(loaded.len(), 1);
+        let loaded = &loaded[0];
+        assert_eq!(loaded.guild, 1);
+        assert_eq!(loaded.channel, Some(4));
+        assert_eq!(loaded.default_volume, 0.5);
+        assert_eq!(loaded.queue[0].position, Duration::from_secs(30));
+        assert_eq!(loaded.queue[0].data.title.as_deref(), Some("track"));
+        assert_eq!(loaded.history[0].finished_at, SystemTime::UNIX_EPOCH);
+
+        storage.remove(1).unwrap();
+        assert!(storage.load().unwrap().is_empty());
+        // removing twice is not an error
+        storage.remove(1).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
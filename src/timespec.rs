@@ -0,0 +1,206 @@
+use core::fmt::Display;
+use core::time::Duration;
+
+use crate::util::Pipe;
+
+const UNITS: [(&str, u64); 8] = [
+    ("y", 365 * 24 * 60 * 60 * 1_000_000_000),
+    ("M", 30 * 24 * 60 * 60 * 1_000_000_000),
+    ("d", 24 * 60 * 60 * 1_000_000_000),
+    ("h", 60 * 60 * 1_000_000_000),
+    ("m", 60 * 1_000_000_000),
+    ("s", 1_000_000_000),
+    ("ms", 1_000_000),
+    ("ns", 1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSpec {
+    Absolute(Duration),
+    Forward(Duration),
+    Backward(Duration),
+}
+impl TimeSpec {
+    pub fn apply(self, current: Duration) -> Duration {
+        use TimeSpec::*;
+        match self {
+            Absolute(d) => d,
+            Forward(d) => current.saturating_add(d),
+            Backward(d) => current.saturating_sub(d),
+        }
+    }
+}
+impl Display for TimeSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use TimeSpec::*;
+        match self {
+            Absolute(d) => write!(f, "{}s", d.as_secs_f32()),
+            Forward(d) => write!(f, "+{}s", d.as_secs_f32()),
+            Backward(d) => write!(f, "-{}s", d.as_secs_f32()),
+        }
+    }
+}
+
+/// parses "[+-]?(%dy)?(%dM)?(%dd)?(%dh)?(%dm)?(%ds)?(%dms)?(%dns)?"
+pub fn parse(s: &str) -> Result<TimeSpec, String> {
+    let (ctor, body): (fn(Duration) -> TimeSpec, _) = match s.as_bytes().first() {
+        Some(b'+') => (TimeSpec::Forward, &s[1..]),
+        Some(b'-') => (TimeSpec::Backward, &s[1..]),
+        _ => (TimeSpec::Absolute, s),
+    };
+
+    if body.is_empty() {
+        return Err("empty time specifier".to_string());
+    }
+
+    let mut rest = body;
+    let mut next_unit = 0;
+    let mut total = Duration::ZERO;
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (number, after) = rest.split_at(digits);
+
+        let letters = after
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(after.len());
+        let (unit, after) = after.split_at(letters);
+
+        if number.is_empty() {
+            return match unit.is_empty() {
+                true => Err(format!("unexpected character in `{}`", rest)),
+                false => Err(format!("missing number before unit `{}`", unit)),
+            };
+        }
+        if unit.is_empty() {
+            return Err(format!("missing unit after `{}`", number));
+        }
+
+        let index = match UNITS.iter().position(|(u, _)| *u == unit) {
+            Some(i) => i,
+            None =>
+                return Err(format!(
+                    "unknown unit `{}` (expected one of {})",
+                    unit,
+                    units()
+                )),
+        };
+        if index < next_unit {
+            return Err(format!(
+                "unit `{}` is duplicated or out of order (expected order: {})",
+                unit,
+                units()
+            ));
+        }
+        next_unit = index + 1;
+
+        let value = number
+            .parse::<u64>()
+            .map_err(|e| format!("invalid number `{}`: {}", number, e))?;
+        let part = value
+            .checked_mul(UNITS[index].1)
+            .map(Duration::from_nanos)
+            .ok_or_else(|| "time specifier overflowed".to_string())?;
+        total = total
+            .checked_add(part)
+            .ok_or_else(|| "time specifier overflowed".to_string())?;
+
+        rest = after;
+    }
+
+    ctor(total).pipe(Ok)
+}
+
+fn units() -> String { UNITS.iter().map(|(u, _)| *u).collect::<Vec<_>>().join(", ") }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute() {
+        assert_eq!(
+            parse("30s"),
+            Ok(TimeSpec::Absolute(Duration::from_secs(30)))
+        );
+        assert_eq!(
+            parse("1h2m3s"),
+            Ok(TimeSpec::Absolute(Duration::from_secs(3723)))
+        );
+        assert_eq!(parse("0s"), Ok(TimeSpec::Absolute(Duration::ZERO)));
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(
+            parse("+10s"),
+            Ok(TimeSpec::Forward(Duration::from_secs(10)))
+        );
+        assert_eq!(
+            parse("-1m500ms"),
+            Ok(TimeSpec::Backward(Duration::from_millis(60_500)))
+        );
+    }
+
+    #[test]
+    fn all_units() {
+        let expected = Duration::from_secs(365 * 86400 + 30 * 86400 + 86400 + 3600 + 60 + 1)
+            + Duration::from_millis(1)
+            + Duration::from_nanos(1);
+
+        assert_eq!(
+            parse("1y1M1d1h1m1s1ms1ns"),
+            Ok(TimeSpec::Absolute(expected))
+        );
+    }
+
+    #[test]
+    fn minute_and_millisecond_are_distinct() {
+        assert_eq!(
+            parse("2m"),
+            Ok(TimeSpec::Absolute(Duration::from_secs(120)))
+        );
+        assert_eq!(
+            parse("2ms"),
+            Ok(TimeSpec::Absolute(Duration::from_millis(2)))
+        );
+        assert_eq!(
+            parse("2M"),
+            Ok(TimeSpec::Absolute(Duration::from_secs(60 * 86400)))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("").is_err());
+        assert!(parse("+").is_err());
+        assert!(parse("10").is_err());
+        assert!(parse("s").is_err());
+        assert!(parse("10x").is_err());
+        assert!(parse("1s1m").is_err());
+        assert!(parse("1s1s").is_err());
+        assert!(parse("1 s").is_err());
+        assert!(parse("+-1s").is_err());
+        assert!(parse("99999999999y").is_err());
+    }
+
+    #[test]
+    fn apply() {
+        let current = Duration::from_secs(30);
+
+        assert_eq!(
+            TimeSpec::Absolute(Duration::from_secs(5)).apply(current),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            TimeSpec::Forward(Duration::from_secs(5)).apply(current),
+            Duration::from_secs(35)
+        );
+        assert_eq!(
+            TimeSpec::Backward(Duration::from_secs(60)).apply(current),
+            Duration::ZERO
+        );
+    }
+}
@@ -34,7 +34,7 @@ impl<T> Pass for T {
 
 pub fn token<R>() -> R
 where R: From<String> {
-    let token = match std::env::var("DISCORD_BOT_TOKEN") {
+    let token = match crate::config::config().token() {
         Ok(o) => o,
         Err(e) => {
             tracing::error!("failed getting token: {}", e);
@@ -45,6 +45,18 @@ where R: From<String> {
     token.into()
 }
 
+pub fn data_dir() -> String {
+    std::env::var("VIVARA_DATA_DIR").unwrap_or_else(|_| "data".to_string())
+}
+
+pub fn media_dir() -> String {
+    std::env::var("VIVARA_MEDIA_DIR").unwrap_or_else(|_| "media".to_string())
+}
+
+pub fn repository_dir() -> String {
+    std::env::var("VIVARA_REPOSITORY_DIR").unwrap_or_else(|_| "repository".to_string())
+}
+
 pub fn reply<S>(msg: S, to: crate::gateway::MessageRef)
 where S: core::fmt::Display {
     reply_inner(format!("err: {}", msg), to)
@@ -55,14 +67,20 @@ where S: core::fmt::Display {
     reply_inner(format!("ok: {}", msg), to)
 }
 
+/// falls back to text if failed sending as an embed
+pub fn reply_embed(embed: crate::gateway::EmbedData, to: crate::gateway::MessageRef) {
+    send_reply(crate::gateway::Payload::Embed(embed), to)
+}
+
 fn reply_inner<S>(msg: S, to: crate::gateway::MessageRef)
 where S: ToString {
+    send_reply(crate::gateway::Payload::Text(msg.to_string()), to)
+}
+
+fn send_reply(payload: crate::gateway::Payload, to: crate::gateway::MessageRef) {
     use actix::ArbiterService;
 
     crate::gateway::Responder::from_registry()
-        .try_send(crate::gateway::Reply {
-            msg: msg.to_string(),
-            to,
-        })
+        .try_send(crate::gateway::Reply { payload, to })
         .expect("failed sending")
 }
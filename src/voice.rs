@@ -0,0 +1,233 @@
+use std::collections::{HashMap, HashSet};
+
+use actix::prelude::{Actor, ArbiterService, Context, Handler, Message, Supervised};
+
+use crate::presence::{ListenersChanged, Presence};
+use crate::util::Pipe;
+
+#[derive(Default)]
+pub struct VoiceStates {
+    // guild -> user -> channel
+    channels: HashMap<u64, HashMap<u64, u64>>,
+    // guild -> channel -> name (voice channels only)
+    names: HashMap<u64, HashMap<u64, String>>,
+    bots: HashSet<u64>,
+}
+impl VoiceStates {
+    fn listeners(&self, guild: u64, channel: u64) -> usize {
+        self.channels
+            .get(&guild)
+            .into_iter()
+            .flatten()
+            .filter(|(u, c)| **c == channel && !self.bots.contains(u))
+            .count()
+    }
+}
+impl Actor for VoiceStates {
+    type Context = Context<Self>;
+}
+impl Handler<VoiceStateChanged> for VoiceStates {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        VoiceStateChanged {
+            guild,
+            user,
+            channel,
+            bot,
+        }: VoiceStateChanged,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        if bot {
+            self.bots.insert(user);
+        }
+
+        let users = self.channels.entry(guild).or_default();
+        let previous = match channel {
+            Some(channel) => users.insert(user, channel),
+            None => users.remove(&user),
+        };
+
+        if previous == channel {
+            return;
+        }
+
+        for channel in [previous, channel].into_iter().flatten() {
+            Presence::from_registry()
+                .try_send(ListenersChanged {
+                    guild,
+                    channel,
+                    listeners: self.listeners(guild, channel),
+                })
+                .expect("failed sending")
+        }
+    }
+}
+impl Handler<GetUserChannel> for VoiceStates {
+    type Result = Option<u64>;
+
+    fn handle(
+        &mut self,
+        GetUserChannel { guild, user }: GetUserChannel,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        self.channels.get(&guild)?.get(&user).copied()
+    }
+}
+impl Handler<GetListeners> for VoiceStates {
+    type Result = usize;
+
+    fn handle(
+        &mut self,
+        GetListeners { guild, channel }: GetListeners,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        self.listeners(guild, channel)
+    }
+}
+impl Handler<VoiceChannelChanged> for VoiceStates {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        VoiceChannelChanged {
+            guild,
+            channel,
+            name,
+        }: VoiceChannelChanged,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let names = self.names.entry(guild).or_default();
+
+        match name {
+            Some(name) => {
+                names.insert(channel, name);
+            },
+            None => {
+                names.remove(&channel);
+            },
+        }
+    }
+}
+impl Handler<ResolveChannel> for VoiceStates {
+    type Result = Result<u64, String>;
+
+    fn handle(
+        &mut self,
+        ResolveChannel { guild, query }: ResolveChannel,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let names = self.names.get(&guild);
+
+        let id = query
+            .strip_prefix("<#")
+            .and_then(|q| q.strip_suffix('>'))
+            .unwrap_or(&query);
+        if let Ok(id) = id.parse() {
+            return match names {
+                // channels may be unknown before receiving GuildCreate
+                Some(n) if !n.is_empty() && !n.contains_key(&id) =>
+                    format!("not a voice channel: <#{}>", id).pipe(Err),
+                _ => Ok(id),
+            };
+        }
+
+        let mut found = names
+            .into_iter()
+            .flatten()
+            .filter(|(_, n)| n.eq_ignore_ascii_case(&query))
+            .map(|(c, _)| *c);
+
+        match (found.next(), found.next()) {
+            (Some(c), None) => Ok(c),
+            (Some(_), Some(_)) => format!("ambiguous channel name: {}", query).pipe(Err),
+            (None, _) => format!("no such voice channel: {}", query).pipe(Err),
+        }
+    }
+}
+impl Supervised for VoiceStates {}
+impl ArbiterService for VoiceStates {}
+
+pub struct VoiceStateChanged {
+    pub guild: u64,
+    pub user: u64,
+    pub channel: Option<u64>,
+    /// bots are not counted as listeners
+    pub bot: bool,
+}
+impl Message for VoiceStateChanged {
+    type Result = ();
+}
+
+/// `name` is `None` if deleted or not a voice channel
+pub struct VoiceChannelChanged {
+    pub guild: u64,
+    pub channel: u64,
+    pub name: Option<String>,
+}
+impl Message for VoiceChannelChanged {
+    type Result = ();
+}
+
+/// resolves id, mention ("<#id>") or name of a voice channel
+pub struct ResolveChannel {
+    pub guild: u64,
+    pub query: String,
+}
+impl Message for ResolveChannel {
+    type Result = Result<u64, String>;
+}
+
+pub struct GetUserChannel {
+    pub guild: u64,
+    pub user: u64,
+}
+impl Message for GetUserChannel {
+    type Result = Option<u64>;
+}
+
+/// number of users (excluding bots) in the channel
+pub struct GetListeners {
+    pub guild: u64,
+    pub channel: u64,
+}
+impl Message for GetListeners {
+    type Result = usize;
+}
+
+#[cfg(test)]
+mod tests {
+    use actix::Actor;
+
+    use super::*;
+
+    #[actix::test]
+    async fn resolve_channel_by_id_mention_or_name() {
+        let voice_states = VoiceStates::default().start();
+
+        for (channel, name) in [(10, Some("Music")), (11, Some("lobby")), (12, None)] {
+            voice_states
+                .send(VoiceChannelChanged {
+                    guild: 1,
+                    channel,
+                    name: name.map(ToString::to_string),
+                })
+                .await
+                .unwrap();
+        }
+
+        let resolve = |query: &str| {
+            voice_states.send(ResolveChannel {
+                guild: 1,
+                query: query.to_string(),
+            })
+        };
+
+        assert_eq!(resolve("10").await.unwrap(), Ok(10));
+        assert_eq!(resolve("<#11>").await.unwrap(), Ok(11));
+        assert_eq!(resolve("music").await.unwrap(), Ok(10));
+        assert!(resolve("12").await.unwrap().is_err());
+        assert!(resolve("general").await.unwrap().is_err());
+    }
+}